@@ -0,0 +1,58 @@
+//! Checks the uniform structs shared by the shaders against the raw structs
+//! written to them from Rust, so their layouts cannot drift apart
+
+use std::{env, fmt::Write, fs, path::PathBuf};
+
+/// Struct declared in the shaders, and the Rust struct mirroring it
+const UNIFORMS: [(&str, &str); 3] = [
+    ("ImageDisplay", "crate::image_display::RawImageDisplay"),
+    ("SourceTiles", "crate::tiles::RawSourceTiles"),
+    ("PassRegion", "crate::region::RawPassRegion"),
+];
+
+fn main() {
+    let path = "src/shader/uniforms.wgsl";
+    println!("cargo:rerun-if-changed={path}");
+    let source = fs::read_to_string(path).unwrap();
+    let module = naga::front::wgsl::parse_str(&source)
+        .unwrap_or_else(|e| panic!("{}", e.emit_to_string_with_path(&source, path)));
+
+    // Every member is at the same offset in both structs, the Rust struct has no
+    // other fields and both are the same size
+    let mut checks = String::new();
+    for (wgsl, rust) in UNIFORMS {
+        let (members, span) = module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match &ty.inner {
+                naga::TypeInner::Struct { members, span } if ty.name.as_deref() == Some(wgsl) => {
+                    Some((members, span))
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("No struct {wgsl} in {path}"));
+
+        let mut fields = String::new();
+        for member in members {
+            let name = member.name.as_deref().unwrap();
+            let offset = member.offset;
+            writeln!(
+                checks,
+                "    assert!(std::mem::offset_of!({rust}, {name}) == {offset}, \
+                 \"{wgsl}.{name} is not at the same offset as in {rust}\");"
+            )
+            .unwrap();
+            write!(fields, "{name}: _, ").unwrap();
+        }
+        writeln!(
+            checks,
+            "    assert!(std::mem::size_of::<{rust}>() == {span}, \
+             \"{wgsl} is not the same size as {rust}\");"
+        )
+        .unwrap();
+        writeln!(checks, "    let _: fn({rust}) = |{rust} {{ {fields}}}| {{}};").unwrap();
+    }
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("uniforms.rs");
+    fs::write(out, format!("const _: () = {{\n{checks}}};\n")).unwrap();
+}
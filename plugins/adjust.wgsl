@@ -0,0 +1,23 @@
+// Brightness, contrast and saturation of the colour, optionally inverted
+
+#include "plugin.wgsl"
+
+struct Params {
+    brightness: f32, // @range(-1, 1) @default(0)
+    contrast: f32, // @range(0, 2) @default(1)
+    saturation: f32, // @range(0, 2) @default(1)
+    invert: u32, // @checkbox
+};
+
+@group(PARAMS_GROUP) @binding(0)
+var<uniform> params: Params;
+
+fn apply(colour: vec4<f32>, pixel: vec2<i32>) -> vec4<f32> {
+    var rgb = (colour.rgb - 0.5) * params.contrast + 0.5 + params.brightness;
+    let luma = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3<f32>(luma), rgb, params.saturation);
+    if params.invert != 0u {
+        rgb = 1.0 - rgb;
+    }
+    return vec4<f32>(rgb, colour.a);
+}
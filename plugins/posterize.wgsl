@@ -0,0 +1,15 @@
+// Reduce each channel to a number of evenly spaced levels
+
+#include "plugin.wgsl"
+
+struct Params {
+    levels: i32, // @range(2, 32) @default(8)
+};
+
+@group(PARAMS_GROUP) @binding(0)
+var<uniform> params: Params;
+
+fn apply(colour: vec4<f32>, pixel: vec2<i32>) -> vec4<f32> {
+    let steps = f32(params.levels - 1);
+    return vec4<f32>(round(colour.rgb * steps) / steps, colour.a);
+}
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, ValueEnum};
+use cs256::{
+    colour::WorkingSpace,
+    cpu,
+    export::{save_image, ExportFormat},
+    graph::{Filter, FilterGraph, FilterNode},
+    headless::HeadlessContext,
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+};
+use image::DynamicImage;
+
+/// Batch process images through the same pipeline as the interactive window
+#[derive(Parser, Debug)]
+#[command(name = "cs256-cli", version)]
+struct Args {
+    /// Input files or glob patterns
+    #[arg(required = true)]
+    inputs: Vec<String>,
+
+    /// Output file for a single input, otherwise an output directory
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Scale factor applied to every image
+    #[arg(short, long, default_value_t = 1.0)]
+    scale: f32,
+
+    /// Scaling mode used for interpolation
+    #[arg(short, long, value_enum, default_value_t = Mode::NearestNeighbour)]
+    mode: Mode,
+
+    /// Point sample when downscaling instead of using mipmaps, only changes the
+    /// nearest neighbour and bilinear modes
+    #[arg(long)]
+    no_mipmaps: bool,
+
+    /// Apply the kernel, normalization and gamma to alpha as a colour channel
+    /// instead of passing it through
+    #[arg(long)]
+    process_alpha: bool,
+
+    /// Colour space the image is processed in
+    #[arg(long, value_enum, default_value_t = Space::LinearSrgb)]
+    working_space: Space,
+
+    /// Gamma correction value, applied in the working space
+    #[arg(short, long, default_value_t = 1.0)]
+    gamma: f32,
+
+    /// How pixels outside of the image are sampled
+    #[arg(long, value_enum, default_value_t = Border::Clamp)]
+    border: Border,
+
+    /// Colour of the constant border as 3 or 4 comma separated linear values
+    #[arg(long, value_parser = parse_colour, default_value = "0,0,0,1")]
+    border_colour: [f32; 4],
+
+    /// Cross correlation kernel, either "laplacian" or rows of comma separated values
+    /// separated by semicolons, a single row of a square number of values is square.
+    /// Repeat to apply kernels in series
+    #[arg(short, long)]
+    kernel: Vec<String>,
+
+    /// Output format, guessed from the output path if not given
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// Quality used for JPEG output
+    #[arg(short, long, default_value_t = 90)]
+    quality: u8,
+
+    /// Backend used to process images
+    #[arg(short, long, value_enum, default_value_t = Backend::Gpu)]
+    backend: Backend,
+
+    /// Force the use of the software fallback adapter
+    #[arg(long)]
+    software: bool,
+}
+
+/// Backends selectable from the command line
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Backend {
+    Gpu,
+    Cpu,
+}
+
+/// Processor for either backend, the cpu backend needs no adapter
+enum Processor {
+    Gpu(Box<HeadlessContext>),
+    Cpu(ImageDisplay),
+}
+
+/// Scaling modes selectable from the command line
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Mode {
+    NearestNeighbour,
+    Bilinear,
+    Bicubic,
+    Mitchell,
+    Lanczos2,
+    Lanczos3,
+    Box,
+}
+
+/// Border modes selectable from the command line
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Border {
+    Clamp,
+    Mirror,
+    Wrap,
+    Constant,
+    Valid,
+}
+
+/// Working spaces selectable from the command line
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Space {
+    LinearSrgb,
+    Srgb,
+    DisplayP3,
+    Acescg,
+}
+
+/// Export formats selectable from the command line
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Format {
+    Png,
+    Png16,
+    Jpeg,
+    Tiff,
+    Tiff16,
+    Exr,
+    Webp,
+}
+
+impl Mode {
+    fn scaling_mode(self) -> ScalingMode {
+        match self {
+            Mode::NearestNeighbour => ScalingMode::NearestNeighbour,
+            Mode::Bilinear => ScalingMode::Bilinear,
+            Mode::Bicubic => ScalingMode::Bicubic,
+            Mode::Mitchell => ScalingMode::Mitchell,
+            Mode::Lanczos2 => ScalingMode::Lanczos2,
+            Mode::Lanczos3 => ScalingMode::Lanczos3,
+            Mode::Box => ScalingMode::Box,
+        }
+    }
+}
+
+impl Border {
+    fn border_mode(self) -> BorderMode {
+        match self {
+            Border::Clamp => BorderMode::Clamp,
+            Border::Mirror => BorderMode::Mirror,
+            Border::Wrap => BorderMode::Wrap,
+            Border::Constant => BorderMode::Constant,
+            Border::Valid => BorderMode::Valid,
+        }
+    }
+}
+
+impl Space {
+    fn working_space(self) -> WorkingSpace {
+        match self {
+            Space::LinearSrgb => WorkingSpace::LinearSrgb,
+            Space::Srgb => WorkingSpace::Srgb,
+            Space::DisplayP3 => WorkingSpace::DisplayP3,
+            Space::Acescg => WorkingSpace::AcesCg,
+        }
+    }
+}
+
+impl Format {
+    fn export_format(self, quality: u8) -> ExportFormat {
+        match self {
+            Format::Png => ExportFormat::Png8,
+            Format::Png16 => ExportFormat::Png16,
+            Format::Jpeg => ExportFormat::Jpeg { quality },
+            Format::Tiff => ExportFormat::Tiff8,
+            Format::Tiff16 => ExportFormat::Tiff16,
+            Format::Exr => ExportFormat::OpenExr,
+            Format::Webp => ExportFormat::WebP,
+        }
+    }
+}
+
+/// Parse a colour argument, the alpha defaults to opaque
+fn parse_colour(colour: &str) -> Result<[f32; 4]> {
+    let values = colour
+        .split(',')
+        .map(|v| v.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .context("Colour values must be numbers")?;
+    match values[..] {
+        [r, g, b] => Ok([r, g, b, 1.0]),
+        [r, g, b, a] => Ok([r, g, b, a]),
+        _ => Err(anyhow!(
+            "Colour must have 3 or 4 values, found {}",
+            values.len()
+        )),
+    }
+}
+
+/// Expand every input pattern into a list of files
+fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        let matches = glob::glob(input)
+            .with_context(|| format!("Invalid pattern {input}"))?
+            .collect::<Result<Vec<PathBuf>, _>>()?;
+        match matches.is_empty() {
+            true => paths.push(PathBuf::from(input)),
+            false => paths.extend(matches),
+        }
+    }
+    Ok(paths)
+}
+
+/// Get the output path of an input file
+fn output_path(args: &Args, input: &Path, single: bool, format: Option<ExportFormat>) -> PathBuf {
+    if single && !args.output.is_dir() && args.output.extension().is_some() {
+        return args.output.clone();
+    }
+    let extension = format.unwrap_or_default().extension();
+    let stem = input.file_stem().unwrap_or_default();
+    args.output.join(stem).with_extension(extension)
+}
+
+/// Check no two inputs are written to the same output, as inputs with the same
+/// file stem in different directories or with different extensions would be
+fn check_outputs(inputs: &[PathBuf], outputs: &[PathBuf]) -> Result<()> {
+    let mut written = HashMap::new();
+    for (input, output) in inputs.iter().zip(outputs) {
+        if let Some(other) = written.insert(output, input) {
+            return Err(anyhow!(
+                "{} and {} would both be written to {}",
+                other.display(),
+                input.display(),
+                output.display()
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl Processor {
+    /// Create a processor for the backend
+    fn new(backend: Backend, software: bool) -> Result<Processor> {
+        Ok(match backend {
+            Backend::Gpu => Processor::Gpu(Box::new(pollster::block_on(HeadlessContext::new(
+                software,
+            ))?)),
+            Backend::Cpu => Processor::Cpu(ImageDisplay::default()),
+        })
+    }
+
+    fn image_display_mut(&mut self) -> &mut ImageDisplay {
+        match self {
+            Processor::Gpu(context) => context.image_display_mut(),
+            Processor::Cpu(display) => display,
+        }
+    }
+
+    /// Process a single file through the pipeline and save it
+    fn process(&mut self, input: &Path, output: &Path, format: Option<ExportFormat>) -> Result<()> {
+        let img = image::open(input)?;
+        match self {
+            Processor::Gpu(context) => {
+                // Images larger than the texture limit are processed in tiles
+                context.load_image(&img)?;
+                pollster::block_on(context.save_output(output, format))
+            }
+            Processor::Cpu(display) => {
+                let processed = DynamicImage::ImageRgba32F(cpu::process(&img, display));
+                save_image(&processed, output, format)
+            }
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let inputs = expand_inputs(&args.inputs)?;
+    let single = inputs.len() == 1;
+    let format = args.format.map(|f| f.export_format(args.quality));
+    let outputs: Vec<_> = inputs
+        .iter()
+        .map(|input| output_path(&args, input, single, format))
+        .collect();
+    check_outputs(&inputs, &outputs)?;
+    if !single || args.output.extension().is_none() {
+        std::fs::create_dir_all(&args.output)?;
+    }
+
+    let mut processor = Processor::new(args.backend, args.software)?;
+
+    // Apply the settings shared by every image
+    let display = processor.image_display_mut();
+    display.size = args.scale;
+    display.gamma = args.gamma;
+    display.working_space = args.working_space.working_space();
+    display.scaling_mode = args.mode.scaling_mode();
+    display.mipmaps = !args.no_mipmaps;
+    display.process_alpha = args.process_alpha;
+    display.border_mode = args.border.border_mode();
+    display.border_colour = args.border_colour;
+    // Kernels run in the order given, followed by gamma correction
+    let mut nodes = args
+        .kernel
+        .iter()
+        .map(|kernel| {
+            Ok(FilterNode::new(
+                Filter::Kernel(Kernel::parse(kernel)?),
+                true,
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    nodes.push(FilterNode::new(Filter::Gamma, true));
+    display.filters = FilterGraph { nodes };
+
+    let mut failed = 0;
+    for (input, output) in inputs.iter().zip(&outputs) {
+        match processor.process(input, output, format) {
+            Ok(()) => println!("{} -> {}", input.display(), output.display()),
+            Err(e) => {
+                eprintln!("{}: {e}", input.display());
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(anyhow!("{failed} of {} images failed", inputs.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,134 @@
+/// Colour space the processing stages operate in. Images are decoded to linear sRGB
+/// when loaded, converted into the working space as the interpolation reads them,
+/// then converted back to linear sRGB by the convert stage after the filters
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum WorkingSpace {
+    /// Linear light with the sRGB primaries
+    LinearSrgb = 0,
+    /// sRGB encoded with its transfer curve, as the image is stored
+    Srgb = 1,
+    /// Display P3 primaries with the sRGB transfer curve
+    DisplayP3 = 2,
+    /// Linear light with the ACES AP1 primaries and D60 white point
+    AcesCg = 3,
+}
+
+// The matrices are repeated in convert.wgsl and interpolation.wgsl,
+// tests/colour.rs checks the copies match
+
+/// Linear sRGB to linear Display P3, both D65
+const SRGB_TO_P3: [[f32; 3]; 3] = [
+    [0.822_462_1, 0.177_538, 0.0],
+    [0.033_194_1, 0.966_805_8, 0.0],
+    [0.017_082_7, 0.072_397_4, 0.910_519_9],
+];
+
+/// Linear Display P3 to linear sRGB
+const P3_TO_SRGB: [[f32; 3]; 3] = [
+    [1.224_940_1, -0.224_940_4, 0.0],
+    [-0.042_056_9, 1.042_057_1, 0.0],
+    [-0.019_637_6, -0.078_636_1, 1.098_273_5],
+];
+
+/// Linear sRGB to ACEScg, adapting D65 to D60 with Bradford
+const SRGB_TO_ACESCG: [[f32; 3]; 3] = [
+    [0.613_097_4, 0.339_523_1, 0.047_379_4],
+    [0.070_193_7, 0.916_353_9, 0.013_452_4],
+    [0.020_615_6, 0.109_569_8, 0.869_815_1],
+];
+
+/// ACEScg to linear sRGB
+const ACESCG_TO_SRGB: [[f32; 3]; 3] = [
+    [1.705_051, -0.621_792_1, -0.083_259],
+    [-0.130_256_4, 1.140_804_7, -0.010_548_3],
+    [-0.024_003_4, -0.128_969, 1.152_972_4],
+];
+
+impl WorkingSpace {
+    /// Every working space, used for selection in the ui
+    pub const ALL: [WorkingSpace; 4] = [
+        WorkingSpace::LinearSrgb,
+        WorkingSpace::Srgb,
+        WorkingSpace::DisplayP3,
+        WorkingSpace::AcesCg,
+    ];
+
+    pub fn from_u32(i: u32) -> WorkingSpace {
+        match i {
+            0 => Self::LinearSrgb,
+            1 => Self::Srgb,
+            2 => Self::DisplayP3,
+            3 => Self::AcesCg,
+            _ => panic!(),
+        }
+    }
+
+    /// Human readable name of the working space
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LinearSrgb => "Linear sRGB",
+            Self::Srgb => "sRGB (Gamma Encoded)",
+            Self::DisplayP3 => "Display P3",
+            Self::AcesCg => "ACEScg",
+        }
+    }
+
+    /// Convert a linear sRGB colour into the working space, alpha is unchanged
+    pub fn from_linear_srgb(&self, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+        let [r, g, b] = match self {
+            Self::LinearSrgb | Self::Srgb => [r, g, b],
+            Self::DisplayP3 => transform(&SRGB_TO_P3, [r, g, b]),
+            Self::AcesCg => transform(&SRGB_TO_ACESCG, [r, g, b]),
+        };
+        match self.encoded() {
+            true => [encode(r), encode(g), encode(b), a],
+            false => [r, g, b, a],
+        }
+    }
+
+    /// Convert a colour in the working space back to linear sRGB, alpha is unchanged
+    pub fn to_linear_srgb(&self, [r, g, b, a]: [f32; 4]) -> [f32; 4] {
+        let [r, g, b] = match self.encoded() {
+            true => [decode(r), decode(g), decode(b)],
+            false => [r, g, b],
+        };
+        let [r, g, b] = match self {
+            Self::LinearSrgb | Self::Srgb => [r, g, b],
+            Self::DisplayP3 => transform(&P3_TO_SRGB, [r, g, b]),
+            Self::AcesCg => transform(&ACESCG_TO_SRGB, [r, g, b]),
+        };
+        [r, g, b, a]
+    }
+
+    /// Whether values are encoded with the sRGB transfer curve rather than linear light
+    pub fn encoded(&self) -> bool {
+        matches!(self, Self::Srgb | Self::DisplayP3)
+    }
+}
+
+/// Multiply a colour by a row major matrix
+fn transform(m: &[[f32; 3]; 3], c: [f32; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * c[0] + row[1] * c[1] + row[2] * c[2])
+}
+
+/// Encode a linear channel with the sRGB transfer curve,
+/// values outside of 0..1 keep their sign
+pub fn encode(c: f32) -> f32 {
+    let x = c.abs();
+    let encoded = match x <= 0.003_130_8 {
+        true => x * 12.92,
+        false => 1.055 * x.powf(1.0 / 2.4) - 0.055,
+    };
+    encoded.copysign(c)
+}
+
+/// Decode a channel encoded with the sRGB transfer curve,
+/// values outside of 0..1 keep their sign
+pub fn decode(c: f32) -> f32 {
+    let x = c.abs();
+    let decoded = match x <= 0.040_45 {
+        true => x / 12.92,
+        false => ((x + 0.055) / 1.055).powf(2.4),
+    };
+    decoded.copysign(c)
+}
@@ -1,41 +1,47 @@
-use std::{iter, mem};
+use std::iter;
 
 use anyhow::{Ok, Result};
 use egui::{Checkbox, ComboBox, Slider};
 use egui_wgpu_backend::{RenderPass, ScreenDescriptor};
 use egui_winit_platform::{Platform, PlatformDescriptor};
 use futures::SinkExt;
-use image::{EncodableLayout, GenericImageView};
+use image::{DynamicImage, ImageFormat};
 use instant::Instant;
-use wgpu::{util::DeviceExt, CommandEncoder, TextureView};
+use wgpu::{CommandEncoder, TextureView};
 
 use crate::{
-    image_display::{ImageDisplay, ImageDisplayWithBuffers, ScalingMode},
+    colour::WorkingSpace,
+    export::ExportFormat,
+    graph::{Filter, FilterGraph, FilterNode},
+    headless::HeadlessContext,
+    image_display::{BorderMode, ScalingMode},
     input::{CursorEvent, InputContext},
-    pipelines::{Binding, Pipelines},
-    stages::{RenderGroup, RenderStages},
+    kernel::Kernel,
+    pipelines::Binding,
+    plugins::{self, Param, ParamKind, ParamValue},
+    shaders::{self, Diagnostic},
     thread_context::ThreadContext,
-    vertex::Vertex,
 };
 
 use super::window::Window;
 
-/// Core of the program, storing device information and all data
+/// Core of the program, presenting the processed image from a HeadlessContext
+/// to a window alongside the user interface
 pub struct GraphicsContext {
     pub surface: wgpu::Surface,
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
-    pub pipelines: Pipelines,
-    pub stages: RenderStages,
-    pub buffers: (wgpu::Buffer, wgpu::Buffer),
-    pub texture_sampler: wgpu::Sampler,
-    pub image_display: ImageDisplayWithBuffers,
+    pub headless: HeadlessContext,
     pub egui: EguiContext,
     pub input: InputContext,
     pub thread: ThreadContext,
-    pub kernel_render_group: RenderGroup,
-    pub texture_render_group: RenderGroup,
+    pub export_format: ExportFormat,
+    /// Watches the shader override directory to reload shaders while running
+    pub shader_watcher: Option<shaders::Watcher>,
+    /// Watches the plugin directory to reload filter plugins while running
+    pub plugin_watcher: Option<shaders::Watcher>,
+    /// Errors of shaders and plugins which failed to load, their previous
+    /// pipelines are kept
+    pub shader_errors: Vec<Diagnostic>,
 }
 
 /// Context containing egui related items
@@ -46,23 +52,6 @@ pub struct EguiContext {
 }
 
 impl GraphicsContext {
-    /// Vertexes spanning screenspace
-    const VERTICES: &'static [Vertex] = &[
-        Vertex::xyz(1.0, 1.0, 0.0),
-        Vertex::xyz(1.0, -1.0, 0.0),
-        Vertex::xyz(-1.0, -1.0, 0.0),
-        Vertex::xyz(-1.0, 1.0, 0.0),
-    ];
-
-    /// Indices for vertexes
-    const INDICES: &'static [u16] = &[0, 3, 1, 1, 3, 2];
-
-    /// Laplacian matrix
-    pub const LAPLACIAN: &'static [f32; 25] = &[
-        -4.0, -1.0, 0.0, -1.0, -4.0, -1.0, 2.0, 3.0, 2.0, -1.0, 0.0, 3.0, 4.0, 3.0, 0.0, -1.0, 2.0,
-        3.0, 2.0, -1.0, -4.0, -1.0, 0.0, -1.0, -4.0,
-    ];
-
     /// Create a new GraphicsContext
     pub async fn new(window: &Window) -> Self {
         let size = window.raw.inner_size();
@@ -86,22 +75,6 @@ impl GraphicsContext {
             .await
             .unwrap();
 
-        // Get the queue and device from the adapter
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    #[cfg(target_arch = "wasm32")]
-                    limits: wgpu::Limits::downlevel_webgl2_defaults(),
-                    #[cfg(not(target_arch = "wasm32"))]
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None,
-            )
-            .await
-            .unwrap();
-
         // Get the surface capabilites and select a target format
         let surface_caps = surface.get_capabilities(&adapter);
         let surface_format = surface_caps
@@ -111,6 +84,29 @@ impl GraphicsContext {
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
 
+        // Create the device, pipelines and stages shared with headless rendering,
+        // only processing the part of the image visible in the window
+        let mut headless = HeadlessContext::from_adapter(
+            &adapter,
+            surface_format,
+            [size.width as f32, size.height as f32],
+        )
+        .await
+        .unwrap_or_else(|e| {
+            // Reported rather than panicking, as an overridden shader may fail to parse
+            log::error!("{e:#}");
+            std::process::exit(1)
+        });
+        headless.viewport = true;
+        // Find the ranges of the whole image a tile each frame, so zooming does not stall
+        headless.ranges_per_frame = 1;
+
+        // Load the filter plugins, showing those which fail with the shader errors
+        let shader_errors = match plugins::dir() {
+            Some(dir) => headless.load_plugins(&dir).await,
+            None => Vec::new(),
+        };
+
         // Create a config and configure the surface to use that config
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -121,7 +117,7 @@ impl GraphicsContext {
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
-        surface.configure(&device, &config);
+        surface.configure(&headless.device, &config);
 
         // Create the egui context and RenderPass for it
         let egui = EguiContext {
@@ -131,185 +127,48 @@ impl GraphicsContext {
                 scale_factor: window.raw.scale_factor(),
                 ..Default::default()
             }),
-            render_pass: RenderPass::new(&device, surface_format, 1),
+            render_pass: RenderPass::new(&headless.device, surface_format, 1),
             last_frame: Instant::now(),
         };
 
-        // Create buffers, pipelines, shaders for use within the program
-        let image_display = ImageDisplayWithBuffers::from_window(&device, &window.raw);
-        let pipelines = Pipelines::new(&device, surface_format, &image_display.layout).await;
-        let texture_sampler = GraphicsContext::create_sampler(&device);
-        let buffers = GraphicsContext::create_buffers(&device);
-        let stages = RenderStages::new();
-
-        // Create the texture for the kernel and write the laplacian matrix to it
-        let kernel_render_group = RenderGroup::new_without_context(
-            (5, 5),
-            &device,
-            wgpu::TextureFormat::Rgba32Float,
-            &texture_sampler,
-            &pipelines,
-        );
-        GraphicsContext::write_kernel_texture(
-            &queue,
-            &kernel_render_group.texture,
-            &GraphicsContext::LAPLACIAN,
-        );
-
-        // Create an empty render group which will be overwritten after the context is created
-        let texture_render_group = RenderGroup::new_without_context(
-            (1, 1),
-            &device,
-            wgpu::TextureFormat::Rgba32Float,
-            &texture_sampler,
-            &pipelines,
-        );
-
-        let mut context = Self {
+        Self {
             surface,
-            device,
-            queue,
             config,
-            pipelines,
-            stages,
-            buffers,
-            texture_sampler,
-            image_display,
+            headless,
             egui,
             input: InputContext::default(),
             thread: ThreadContext::default(),
-            kernel_render_group,
-            texture_render_group,
-        };
-
-        // Load the texture into the empty render group
-        context
-            .load_texture(include_bytes!("../assets/raytrace.jpg"))
-            .unwrap();
-
-        context
-    }
-
-    /// Create vertex and index buffers
-    pub fn create_buffers(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("vertex_buf"),
-            contents: bytemuck::cast_slice(GraphicsContext::VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("index_buf"),
-            contents: bytemuck::cast_slice(GraphicsContext::INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        (vertex_buffer, index_buffer)
-    }
-
-    /// Create the sampler used for all textures
-    pub fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
-        device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        })
+            export_format: ExportFormat::default(),
+            shader_watcher: shaders::override_dir().map(shaders::Watcher::new),
+            plugin_watcher: plugins::dir().map(shaders::Watcher::new),
+            shader_errors,
+        }
     }
 
-    /// Load a new image into the program from bytes
-    pub fn load_texture(&mut self, bytes: &[u8]) -> Result<()> {
-        let img = image::load_from_memory(bytes)?;
-        let rgba = img.to_rgba8();
-        let dimensions = img.dimensions();
-
-        let size = wgpu::Extent3d {
-            width: dimensions.0,
-            height: dimensions.1,
-            depth_or_array_layers: 1,
+    /// Reload the shaders and plugins when any have changed in the override or
+    /// plugin directories, as plugins include the shared shaders. The errors are
+    /// replaced with those of the latest reload
+    pub fn reload_shaders(&mut self) {
+        let changed: Vec<String> = [&mut self.shader_watcher, &mut self.plugin_watcher]
+            .into_iter()
+            .flatten()
+            .flat_map(|watcher| watcher.changed())
+            .collect();
+        if changed.is_empty() {
+            return;
+        }
+        log::info!("Reloading shaders after {} changed", changed.join(", "));
+        self.shader_errors = match self.shader_watcher {
+            Some(_) => pollster::block_on(self.headless.reload_shaders()),
+            None => Vec::new(),
         };
-        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8UnormSrgb,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[],
-        });
-
-        self.queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &self.pipelines.bind_group_layouts.bgra8unormsrgb,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&self.texture_sampler),
-                },
-            ],
-            label: None,
-        });
-
-        self.texture_render_group = RenderGroup::from_raw(texture, view, bind_group);
-        self.image_display.set_changed();
-
-        Ok(())
-    }
-
-    /// Load a new kernel texture
-    pub fn write_kernel_texture(queue: &wgpu::Queue, texture: &wgpu::Texture, data: &[f32; 25]) {
-        let mut normalized_values = Vec::new();
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &{
-                for i in data {
-                    let rgba = [
-                        f32::max(0.0, f32::min(1.0, (*i / 256.0) + 0.5)),
-                        0.0,
-                        0.0,
-                        0.0,
-                    ];
-                    normalized_values.extend_from_slice(&rgba);
-                }
-                normalized_values.as_bytes()
-            },
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * 4 * 5),
-                rows_per_image: Some(5),
-            },
-            texture.size(),
-        );
+        if let Some(dir) = plugins::dir() {
+            let errors = pollster::block_on(self.headless.load_plugins(&dir));
+            self.shader_errors.extend(errors);
+        }
+        for e in &self.shader_errors {
+            log::error!("Failed to reload shader {e}");
+        }
     }
 
     /// Resize window callback
@@ -317,129 +176,24 @@ impl GraphicsContext {
         if width > 0 && height > 0 {
             self.config.width = width;
             self.config.height = height;
-            self.surface.configure(&self.device, &self.config);
-            self.image_display.internal.window_size = [width as f32, height as f32];
+            self.surface.configure(&self.headless.device, &self.config);
+            self.headless.image_display_mut().window_size = [width as f32, height as f32];
         }
     }
 
-    pub fn image_display(&self) -> &ImageDisplay {
-        &self.image_display.internal
-    }
-
-    pub fn image_display_mut(&mut self) -> &mut ImageDisplay {
-        &mut self.image_display.internal
-    }
-
-    /// Get the target resolution of the interpolation
-    pub fn scaled_texture_size(&self) -> (u32, u32) {
-        let original_size = self.texture_render_group.size();
-        let scale = self.image_display().size;
-        (
-            u32::max(1, (original_size.0 as f32 * scale).floor() as u32),
-            u32::max(1, (original_size.1 as f32 * scale).floor() as u32),
-        )
-    }
-
-    /// Get the max scale possible given the original size, and the device limits
-    pub fn max_scale(&self) -> f32 {
-        let size = self.texture_render_group.size();
-        let base_size = u32::max(size.0, size.1) as f32;
-        let max_size = self.device.limits().max_texture_dimension_2d as f32;
-        max_size / base_size
-    }
-
     /// Perform all render tasks per frame
     pub fn render(&mut self, window: &winit::window::Window) -> Result<()> {
-        self.image_display.bind(self);
+        self.headless.image_display.bind(&self.headless);
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+        let mut encoder =
+            self.headless
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
 
-        let texture_dims = self.scaled_texture_size();
-
-        if self.image_display.changed {
-            let mut stages = mem::take(&mut self.stages);
-            stages.update_resolution(&self, texture_dims);
-            self.stages = stages;
-
-            // Interpolate image
-            self.render_pass(
-                &mut encoder,
-                &self.pipelines.interpolation,
-                &self.stages.output_staging().view,
-                &[
-                    Binding(0, &self.texture_render_group.bind_group),
-                    Binding(1, &self.image_display.bind_group),
-                ],
-                false,
-            );
-
-            // Generate the lookup table
-            self.render_pass(
-                &mut encoder,
-                &self.pipelines.gamma_lut,
-                &self.stages.gamma_lut().view,
-                &[Binding(0, &self.image_display.bind_group)],
-                false,
-            );
-
-            if self.image_display().cross_correlation {
-                // Apply kernel to interpolated image
-                self.render_pass(
-                    &mut encoder,
-                    &self.pipelines.kernel,
-                    &self.stages.kerneled().view,
-                    &[
-                        Binding(0, &self.stages.output_staging().bind_group),
-                        Binding(1, &self.image_display.bind_group),
-                        Binding(2, &self.kernel_render_group.bind_group),
-                    ],
-                    false,
-                );
-
-                // Get Min Max from the kernelled image
-                self.render_pass(
-                    &mut encoder,
-                    &self.pipelines.min_max,
-                    &self.stages.min_max().view,
-                    &[
-                        Binding(0, &self.stages.kerneled().bind_group),
-                        Binding(1, &self.image_display.bind_group),
-                        Binding(2, &self.kernel_render_group.bind_group),
-                    ],
-                    false,
-                );
-
-                // Normalize the image based on the Min Max found
-                self.render_pass(
-                    &mut encoder,
-                    &self.pipelines.normalize,
-                    &self.stages.output_staging().view,
-                    &[
-                        Binding(0, &self.stages.kerneled().bind_group),
-                        Binding(1, &self.image_display.bind_group),
-                        Binding(2, &self.stages.min_max().bind_group),
-                    ],
-                    false,
-                );
-            }
-            self.image_display.clear_changed();
-        }
-
-        self.render_pass(
-            &mut encoder,
-            &self.pipelines.gamma,
-            &self.stages.gamma().view,
-            &[
-                Binding(0, &self.stages.output_staging().bind_group),
-                Binding(1, &self.image_display.bind_group),
-                Binding(2, &self.stages.gamma_lut().bind_group),
-            ],
-            false,
-        );
+        // Run the processing stages
+        self.headless.process(&mut encoder);
 
         // Get current screen texture
         let output = self.surface.get_current_texture()?;
@@ -448,13 +202,14 @@ impl GraphicsContext {
             .create_view(&wgpu::TextureViewDescriptor::default());
 
         // Render the modified tex to screenspace
-        self.render_pass(
+        self.headless.render_pass(
             &mut encoder,
-            &self.pipelines.output,
+            &self.headless.pipelines.output,
             &output_view,
             &[
-                Binding(0, &&self.stages.gamma().bind_group),
-                Binding(1, &self.image_display.bind_group),
+                Binding(0, &self.headless.stages.processed().bind_group),
+                Binding(1, &self.headless.image_display.bind_group),
+                Binding(2, &self.headless.stages.passes().output.bind_group),
             ],
             true,
         );
@@ -463,7 +218,7 @@ impl GraphicsContext {
         self.render_egui(&mut encoder, &output_view, window);
 
         // Submit all work to queue and present
-        self.queue.submit(iter::once(encoder.finish()));
+        self.headless.queue.submit(iter::once(encoder.finish()));
         output.present();
 
         self.egui.last_frame = Instant::now();
@@ -471,49 +226,38 @@ impl GraphicsContext {
         Ok(())
     }
 
-    /// Perform a render pass, given a render target and bind operations
-    pub fn render_pass(
-        &self,
-        encoder: &mut CommandEncoder,
-        pipeline: &wgpu::RenderPipeline,
-        tex_out: &wgpu::TextureView,
-        bindings: &[Binding],
-        clear: bool,
-    ) {
-        // Begin render pass
-        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("Render Pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &tex_out,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: {
-                        match clear {
-                            true => wgpu::LoadOp::Clear(wgpu::Color {
-                                r: self.image_display().background_colour[0] as f64,
-                                g: self.image_display().background_colour[1] as f64,
-                                b: self.image_display().background_colour[2] as f64,
-                                a: self.image_display().background_colour[3] as f64,
-                            }),
-                            false => wgpu::LoadOp::Load,
-                        }
-                    },
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            occlusion_query_set: None,
-            timestamp_writes: None,
-        });
+    /// Process the whole image in tiles, read it back and save it to a file chosen
+    /// by the user
+    pub fn save_output(&mut self, window: &winit::window::Window) {
+        let readback = self.headless.start_read_output();
 
-        // Bind everything and draw
-        render_pass.set_pipeline(&pipeline);
-        for Binding(index, bind_group) in bindings {
-            render_pass.set_bind_group(*index, bind_group, &[])
-        }
-        render_pass.set_vertex_buffer(0, self.buffers.0.slice(..));
-        render_pass.set_index_buffer(self.buffers.1.slice(..), wgpu::IndexFormat::Uint16);
-        render_pass.draw_indexed(0..GraphicsContext::INDICES.len() as u32, 0, 0..1);
+        // Return to the viewport before the frame being recorded is submitted
+        self.headless.render();
+        let readback = match readback {
+            Result::Ok(readback) => readback.map(),
+            Err(e) => return log::error!("Failed to read back image: {e}"),
+        };
+
+        let format = self.export_format;
+        let dialog = rfd::AsyncFileDialog::new()
+            .add_filter(format.name(), &[format.extension()])
+            .set_file_name(format!("output.{}", format.extension()))
+            .set_parent(window)
+            .save_file();
+
+        // The readback resolves once the next frame has been submitted
+        self.thread.execute(async move {
+            let result = async {
+                let img = DynamicImage::ImageRgba32F(readback.await?);
+                if let Some(file) = dialog.await {
+                    file.write(&format.encode(&img)?).await?;
+                }
+                Ok(())
+            };
+            if let Err(e) = result.await {
+                log::error!("Failed to save image: {e}");
+            }
+        });
     }
 
     /// Render the ui using egui
@@ -530,7 +274,7 @@ impl GraphicsContext {
 
         self.egui.platform.begin_frame();
 
-        let cloned = self.image_display().clone();
+        let cloned = self.headless.image_display().clone();
 
         // Draw all UI
         let ctx = &self.egui.platform.context();
@@ -540,7 +284,7 @@ impl GraphicsContext {
                 // Open file button
                 if (ui.button("Open file")).clicked() {
                     let dialog = rfd::AsyncFileDialog::new()
-                        .add_filter("img", &["png", "jpg"])
+                        .add_filter("img", &image_extensions())
                         .set_parent(&window)
                         .pick_file();
 
@@ -555,79 +299,179 @@ impl GraphicsContext {
                     });
                 }
 
+                // Save file button and export format selection
+                ui.horizontal(|ui| {
+                    if ui.button("Save file").clicked() {
+                        self.save_output(window);
+                    }
+
+                    ComboBox::from_id_source("export_format")
+                        .selected_text(self.export_format.name())
+                        .show_ui(ui, |ui| {
+                            for format in ExportFormat::ALL {
+                                ui.selectable_value(&mut self.export_format, format, format.name());
+                            }
+                        });
+
+                    if let ExportFormat::Jpeg { quality } = &mut self.export_format {
+                        ui.add(Slider::new(quality, 1..=100).text("Quality"));
+                    }
+                });
+
                 // Position Boxes
                 {
-                    ui.add(egui::DragValue::new(&mut self.image_display_mut().pos[0]).speed(1.0));
-                    ui.add(egui::DragValue::new(&mut self.image_display_mut().pos[1]).speed(1.0));
+                    ui.add(
+                        egui::DragValue::new(&mut self.headless.image_display_mut().pos[0])
+                            .speed(1.0),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut self.headless.image_display_mut().pos[1])
+                            .speed(1.0),
+                    );
                 }
 
+                // Working space selection box, gamma is applied in the working space
+                ComboBox::from_id_source("working_space")
+                    .selected_text(self.headless.image_display().working_space.name())
+                    .show_ui(ui, |ui| {
+                        for space in WorkingSpace::ALL {
+                            ui.selectable_value(
+                                &mut self.headless.image_display_mut().working_space,
+                                space,
+                                space.name(),
+                            );
+                        }
+                    });
+
                 // Gamma correction slider
                 ui.add(
-                    Slider::new(&mut self.image_display_mut().gamma, 0.0..=2.0)
+                    Slider::new(&mut self.headless.image_display_mut().gamma, 0.0..=2.0)
                         .text("Gamma Correction"),
                 );
 
                 // Image side slider
-                let max_scale = self.max_scale();
+                let max_scale = self.headless.max_scale();
                 ui.add(
-                    Slider::new(&mut self.image_display_mut().size, 0.0..=max_scale)
+                    Slider::new(&mut self.headless.image_display_mut().size, 0.0..=max_scale)
                         .text("Image Size"),
                 );
 
                 // Scaling mode selection box
                 ComboBox::from_label("")
-                    .selected_text(format!("{:?}", &mut self.image_display_mut().scaling_mode))
+                    .selected_text(self.headless.image_display().scaling_mode.name())
                     .show_ui(ui, |ui| {
-                        ui.selectable_value(
-                            &mut self.image_display_mut().scaling_mode,
-                            ScalingMode::NearestNeighbour,
-                            "Nearest Neighbour",
-                        );
-                        ui.selectable_value(
-                            &mut self.image_display_mut().scaling_mode,
-                            ScalingMode::Bilinear,
-                            "Bi-Linear",
-                        );
+                        for mode in ScalingMode::ALL {
+                            ui.selectable_value(
+                                &mut self.headless.image_display_mut().scaling_mode,
+                                mode,
+                                mode.name(),
+                            );
+                        }
                     });
 
-                // Cross correlation
-                {
+                // Mipmaps only change the point sampled scaling modes
+                if self.headless.image_display().scaling_mode <= ScalingMode::Bilinear {
                     ui.add(Checkbox::new(
-                        &mut self.image_display_mut().cross_correlation,
-                        "Cross Correlation",
+                        &mut self.headless.image_display_mut().mipmaps,
+                        "Mipmaps",
                     ));
+                }
 
-                    if self.image_display().cross_correlation {
-                        ui.separator();
+                // Alpha is passed through the later stages unless processed
+                ui.add(Checkbox::new(
+                    &mut self.headless.image_display_mut().process_alpha,
+                    "Process Alpha",
+                ));
+
+                // Border mode selection box, with a colour for the constant border
+                ComboBox::from_id_source("border_mode")
+                    .selected_text(format!(
+                        "{:?} Border",
+                        self.headless.image_display().border_mode
+                    ))
+                    .show_ui(ui, |ui| {
+                        for mode in BorderMode::ALL {
+                            ui.selectable_value(
+                                &mut self.headless.image_display_mut().border_mode,
+                                mode,
+                                format!("{mode:?}"),
+                            );
+                        }
+                    });
+                if self.headless.image_display().border_mode == BorderMode::Constant {
+                    let colour = &mut self.headless.image_display_mut().border_colour;
+                    let mut rgb = [colour[0], colour[1], colour[2]];
+                    ui.horizontal(|ui| {
+                        ui.label("Border Colour");
+                        egui::color_picker::color_edit_button_rgb(ui, &mut rgb);
+                    });
+                    colour[..3].copy_from_slice(&rgb);
+                }
 
-                        for row in 0..5 {
+                // Filter graph, each enabled filter runs on the result of the one above
+                ui.collapsing("Filters", |ui| {
+                    let plugins: Vec<_> =
+                        self.headless.plugins.iter().map(Filter::plugin).collect();
+                    let loaded: Vec<_> = self
+                        .headless
+                        .plugins
+                        .iter()
+                        .map(|p| p.name.clone())
+                        .collect();
+                    let filters = &mut self.headless.image_display_mut().filters;
+                    let mut edit: Option<(usize, NodeEdit)> = None;
+                    for (i, node) in filters.nodes.iter_mut().enumerate() {
+                        ui.push_id(i, |ui| {
                             ui.horizontal(|ui| {
-                                for col in 0..5 {
-                                    ui.add(
-                                        egui::DragValue::new(
-                                            &mut self.image_display_mut().kernel[5 * row + col],
-                                        )
-                                        .speed(0.01)
-                                        .clamp_range(-10.0..=10.0),
-                                    );
+                                ui.checkbox(&mut node.enabled, node.filter.name());
+                                if ui.small_button("Up").clicked() {
+                                    edit = Some((i, FilterGraph::move_up));
+                                }
+                                if ui.small_button("Down").clicked() {
+                                    edit = Some((i, FilterGraph::move_down));
+                                }
+                                if ui.small_button("Duplicate").clicked() {
+                                    edit = Some((i, FilterGraph::duplicate));
+                                }
+                                if ui.small_button("Remove").clicked() {
+                                    edit = Some((i, FilterGraph::remove));
                                 }
                             });
-                        }
-                        if ui.button("Update").clicked() {
-                            GraphicsContext::write_kernel_texture(
-                                &self.queue,
-                                &self.kernel_render_group.texture,
-                                &self.image_display.internal.kernel,
-                            );
-                            self.image_display.set_changed();
-                        }
-                        ui.separator();
+                            if !node.enabled {
+                                return;
+                            }
+                            ui.indent("filter", |ui| match &mut node.filter {
+                                Filter::Kernel(kernel) => kernel_editor(ui, kernel),
+                                Filter::Gamma => {}
+                                Filter::Plugin { name, params } => match loaded.contains(name) {
+                                    true => params_editor(ui, params),
+                                    false => {
+                                        ui.weak("Not loaded");
+                                    }
+                                },
+                            });
+                        });
                     }
-                }
+                    if let Some((i, edit)) = edit {
+                        edit(filters, i);
+                    }
+
+                    // New filters are added to the end of the graph
+                    ComboBox::from_id_source("add_filter")
+                        .selected_text("Add Filter")
+                        .show_ui(ui, |ui| {
+                            let builtin = [Filter::Kernel(Kernel::default()), Filter::Gamma];
+                            for filter in builtin.into_iter().chain(plugins) {
+                                if ui.selectable_label(false, filter.name()).clicked() {
+                                    filters.nodes.push(FilterNode::new(filter, true));
+                                }
+                            }
+                        });
+                });
 
                 // Background colour wheel
                 {
-                    let colour = &mut self.image_display_mut().background_colour;
+                    let colour = &mut self.headless.image_display_mut().background_colour;
                     let mut rgb = [colour[0], colour[1], colour[2]];
                     egui::color_picker::color_edit_button_rgb(ui, &mut rgb);
                     colour[0] = rgb[0];
@@ -637,20 +481,30 @@ impl GraphicsContext {
 
                 // Reset to defaults button
                 if ui.button("Reset Default").clicked() {
-                    self.image_display_mut().reset_default();
-                    GraphicsContext::write_kernel_texture(
-                        &self.queue,
-                        &self.kernel_render_group.texture,
-                        &self.image_display.internal.kernel,
-                    );
+                    self.headless.image_display_mut().reset_default();
                 }
 
                 self.input.mouse_over_ui = ui.ui_contains_pointer();
             });
 
+        // Errors of shaders which failed to reload, while their previous pipeline runs
+        if !self.shader_errors.is_empty() {
+            egui::TopBottomPanel::bottom("shader_errors").show(ctx, |ui| {
+                ui.heading("Shader Errors");
+                for error in &self.shader_errors {
+                    let location = match error.location {
+                        Some((line, column)) => format!("{}:{line}:{column}", error.file),
+                        None => error.file.clone(),
+                    };
+                    ui.colored_label(egui::Color32::LIGHT_RED, location);
+                    ui.monospace(&error.message);
+                }
+            });
+        }
+
         // Check if has changed
-        if *self.image_display() != cloned {
-            self.image_display.set_changed()
+        if *self.headless.image_display() != cloned {
+            self.headless.image_display.set_changed()
         }
 
         let full_output = self.egui.platform.end_frame(Some(window));
@@ -665,9 +519,14 @@ impl GraphicsContext {
 
         let render_pass = &mut self.egui.render_pass;
         render_pass
-            .add_textures(&self.device, &self.queue, &tdelta)
+            .add_textures(&self.headless.device, &self.headless.queue, &tdelta)
             .unwrap();
-        render_pass.update_buffers(&self.device, &self.queue, &paint_jobs, &screen_descriptor);
+        render_pass.update_buffers(
+            &self.headless.device,
+            &self.headless.queue,
+            &paint_jobs,
+            &screen_descriptor,
+        );
         render_pass
             .execute(encoder, view, &paint_jobs, &screen_descriptor, None)
             .unwrap();
@@ -684,18 +543,97 @@ impl GraphicsContext {
             }
             CursorEvent::Position(pos) => {
                 if input.mouse_pressed {
-                    self.image_display.internal.pos[0] += pos.x - input.last_mouse_pos.x;
-                    self.image_display.internal.pos[1] += pos.y - input.last_mouse_pos.y;
+                    self.headless.image_display.internal.pos[0] += pos.x - input.last_mouse_pos.x;
+                    self.headless.image_display.internal.pos[1] += pos.y - input.last_mouse_pos.y;
                 }
                 input.last_mouse_pos = pos;
             }
             CursorEvent::Scroll(scroll) => {
-                self.image_display_mut().size +=
-                    scroll * (self.image_display().size * self.image_display().size + 1.1).log10();
-                self.image_display_mut().size =
-                    f32::min(f32::max(self.image_display().size, 0.001), self.max_scale());
-                self.image_display.set_changed();
+                self.headless.image_display_mut().size += scroll
+                    * (self.headless.image_display().size * self.headless.image_display().size
+                        + 1.1)
+                        .log10();
+                self.headless.image_display_mut().size = f32::min(
+                    f32::max(self.headless.image_display().size, 0.001),
+                    self.headless.max_scale(),
+                );
+                self.headless.image_display.set_changed();
+            }
+        }
+    }
+}
+
+/// Edit of the filter graph at the index of a node
+type NodeEdit = fn(&mut FilterGraph, usize);
+
+/// Extensions of every format an image can be opened from
+fn image_extensions() -> Vec<&'static str> {
+    ImageFormat::all()
+        .filter(|format| format.can_read() && format.reading_enabled())
+        .flat_map(ImageFormat::extensions_str)
+        .copied()
+        .collect()
+}
+
+/// Edit the size and weights of a kernel, stepping over even sizes in the
+/// direction of the drag
+fn kernel_editor(ui: &mut egui::Ui, kernel: &mut Kernel) {
+    let old_size = kernel.size();
+    let mut size = old_size;
+    ui.horizontal(|ui| {
+        ui.label("Kernel Size");
+        ui.add(egui::DragValue::new(&mut size.0).clamp_range(1..=Kernel::MAX_SIZE));
+        ui.label("x");
+        ui.add(egui::DragValue::new(&mut size.1).clamp_range(1..=Kernel::MAX_SIZE));
+    });
+    if size != old_size {
+        let odd = |new: u32, old: u32| match (new % 2 == 1, new > old) {
+            (true, _) => new,
+            (false, true) => new + 1,
+            (false, false) => new - 1,
+        };
+        let size = (odd(size.0, old_size.0), odd(size.1, old_size.1));
+        if let Err(e) = kernel.resize(size) {
+            log::error!("{e}");
+        }
+    }
+
+    let (width, height) = kernel.size();
+    egui::ScrollArea::both().max_height(300.0).show(ui, |ui| {
+        for row in 0..height as usize {
+            ui.horizontal(|ui| {
+                for col in 0..width as usize {
+                    ui.add(
+                        egui::DragValue::new(&mut kernel.weights_mut()[width as usize * row + col])
+                            .speed(0.01),
+                    );
+                }
+            });
+        }
+    });
+}
+
+/// Edit the parameters of a plugin with a control generated for each
+fn params_editor(ui: &mut egui::Ui, params: &mut [Param]) {
+    for param in params.iter_mut() {
+        let name = param.name.as_str();
+        match (param.kind, &mut param.value) {
+            (ParamKind::Float { min, max }, ParamValue::Float(v)) => {
+                ui.add(Slider::new(v, min..=max).text(name))
             }
+            (ParamKind::Int { min, max }, ParamValue::Int(v)) => {
+                ui.add(Slider::new(v, min..=max).text(name))
+            }
+            (ParamKind::Uint { min, max }, ParamValue::Uint(v)) => {
+                ui.add(Slider::new(v, min..=max).text(name))
+            }
+            (ParamKind::Bool, ParamValue::Bool(v)) => ui.add(Checkbox::new(v, name)),
+            _ => unreachable!("parameter value of a different kind"),
+        };
+    }
+    if ui.button("Reset").clicked() {
+        for param in params {
+            param.value = param.default;
         }
     }
 }
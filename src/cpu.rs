@@ -0,0 +1,558 @@
+use std::f32::consts::PI;
+
+use image::{DynamicImage, Rgba32FImage};
+
+use crate::{
+    colour,
+    graph::Filter,
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+    tiles::SourcePrecision,
+};
+
+/// Largest per channel difference expected between the CPU and GPU results of
+/// every stage before the gamma lookup. Differences come from the GPU decoding
+/// sRGB textures, fusing multiply adds and approximating `pow`
+pub const TOLERANCE: f32 = 2e-3;
+
+/// Largest per channel difference expected between the CPU and GPU results of the
+/// final gamma stage. A value within TOLERANCE of a rounding boundary may select the
+/// neighbouring entry of the lookup table, so this is the largest step between
+/// entries of the table for the gamma value
+pub fn tolerance(gamma: f32) -> f32 {
+    let lut = gamma_lut(gamma);
+    let step = lut
+        .windows(2)
+        .map(|w| (w[1] - w[0]).abs())
+        .fold(0.0, f32::max);
+    step + TOLERANCE
+}
+
+/// How pixels outside of an image are sampled
+#[derive(Debug, Clone, Copy)]
+pub struct Border {
+    pub mode: BorderMode,
+    pub colour: [f32; 4],
+}
+
+impl Border {
+    /// Get the border settings of an ImageDisplay, with the colour in the working space
+    pub fn from_display(display: &ImageDisplay) -> Border {
+        Border {
+            mode: display.border_mode,
+            colour: display
+                .working_space
+                .from_linear_srgb(display.border_colour),
+        }
+    }
+}
+
+impl Default for Border {
+    fn default() -> Self {
+        Border {
+            mode: BorderMode::Clamp,
+            colour: [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+/// Run every processing stage on the CPU, mirroring the stage chain of the
+/// HeadlessContext and returning the result of the processed stage. Plugins
+/// are shaders which only run on the GPU, so their filters are skipped
+pub fn process(source: &DynamicImage, display: &ImageDisplay) -> Rgba32FImage {
+    let mut staging = interpolate(to_linear(source), display);
+    let border = Border::from_display(display);
+    let lut = gamma_lut(display.gamma);
+    let exact = SourcePrecision::of(source).exact_gamma();
+
+    let alpha = display.process_alpha;
+
+    for filter in display.filters.enabled() {
+        staging = match filter {
+            Filter::Kernel(kernel) => {
+                let kerneled = apply_kernel(&staging, kernel, border, alpha);
+                let min_max = min_max(&kerneled);
+                normalize(&kerneled, min_max, alpha)
+            }
+            Filter::Gamma => match exact {
+                true => exact_gamma_correction(&staging, display.gamma, alpha),
+                false => gamma_correction(&staging, &lut, alpha),
+            },
+            Filter::Plugin { .. } => staging,
+        };
+    }
+    convert(&staging, |p| display.working_space.to_linear_srgb(p))
+}
+
+/// Interpolate a linear image to the scaled size with the scaling mode of the display,
+/// converting into the working space. Filtering is done on premultiplied colours,
+/// so transparent pixels do not bleed their colour into their neighbours
+pub fn interpolate(linear: Rgba32FImage, display: &ImageDisplay) -> Rgba32FImage {
+    let dims = display.scaled_size(linear.dimensions());
+    let border = Border::from_display(display);
+    let border = Border {
+        colour: premultiply_pixel(border.colour),
+        ..border
+    };
+
+    // Without mipmaps the chain is only the image itself. The mip chain is always
+    // averaged in linear light, as it is generated once when the image is loaded
+    let levels = match display.mipmaps {
+        true => mip_chain(linear),
+        false => vec![linear],
+    };
+    let space = display.working_space;
+    let levels: Vec<_> = levels
+        .iter()
+        .map(|level| premultiply(&convert(level, |p| space.from_linear_srgb(p))))
+        .collect();
+
+    let interpolated = match display.scaling_mode {
+        ScalingMode::NearestNeighbour => nearest_neighbour(&levels, dims, display.size, border),
+        ScalingMode::Bilinear => bilinear(&levels, dims, display.size, border),
+        mode => resample(&levels[0], dims, display.size, mode, border),
+    };
+    unpremultiply(&interpolated)
+}
+
+/// Convert every pixel of an image between colour spaces
+pub fn convert(img: &Rgba32FImage, f: impl Fn([f32; 4]) -> [f32; 4] + Sync) -> Rgba32FImage {
+    generate(img.dimensions(), |x, y| f(img.get_pixel(x, y).0))
+}
+
+/// Multiply the colour channels of a pixel by its alpha
+fn premultiply_pixel([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    [r * a, g * a, b * a, a]
+}
+
+/// Multiply the colour channels of every pixel by its alpha
+pub fn premultiply(img: &Rgba32FImage) -> Rgba32FImage {
+    generate(img.dimensions(), |x, y| {
+        premultiply_pixel(img.get_pixel(x, y).0)
+    })
+}
+
+/// Divide the colour channels of every pixel by its alpha, clamping alpha as the
+/// filters can ring outside of 0..1. A transparent pixel has no colour
+pub fn unpremultiply(img: &Rgba32FImage) -> Rgba32FImage {
+    generate(img.dimensions(), |x, y| {
+        let [r, g, b, a] = img.get_pixel(x, y).0;
+        let a = a.clamp(0.0, 1.0);
+        match a <= 0.0 {
+            true => [0.0; 4],
+            false => [r / a, g / a, b / a, a],
+        }
+    })
+}
+
+/// Convert an image to linear floats as the source tiles hold it. 8 and 16-bit
+/// images are sRGB encoded, float images are already linear and kept unclamped
+pub fn to_linear(source: &DynamicImage) -> Rgba32FImage {
+    let decode =
+        |[r, g, b, a]: [f32; 4]| [colour::decode(r), colour::decode(g), colour::decode(b), a];
+    match SourcePrecision::of(source) {
+        SourcePrecision::Srgb8 => {
+            let rgba = source.to_rgba8();
+            generate(rgba.dimensions(), |x, y| {
+                decode(rgba.get_pixel(x, y).0.map(|c| c as f32 / 255.0))
+            })
+        }
+        SourcePrecision::Srgb16 => {
+            let rgba = source.to_rgba16();
+            generate(rgba.dimensions(), |x, y| {
+                decode(rgba.get_pixel(x, y).0.map(|c| c as f32 / 65535.0))
+            })
+        }
+        SourcePrecision::Float => source.to_rgba32f(),
+    }
+}
+
+/// Reflect a coordinate into 0..size, repeating the edge pixel
+fn mirror(i: i32, size: i32) -> i32 {
+    let m = i.rem_euclid(size * 2);
+    match m >= size {
+        true => size * 2 - 1 - m,
+        false => m,
+    }
+}
+
+/// Sample the image at a pixel coordinate, resolving coordinates
+/// outside of the image with the border mode
+fn sample_pixel(img: &Rgba32FImage, x: i32, y: i32, border: Border) -> [f32; 4] {
+    let (width, height) = (img.width() as i32, img.height() as i32);
+    let inside = (0..width).contains(&x) && (0..height).contains(&y);
+    let (x, y) = match border.mode {
+        BorderMode::Mirror => (mirror(x, width), mirror(y, height)),
+        BorderMode::Wrap => (x.rem_euclid(width), y.rem_euclid(height)),
+        BorderMode::Constant if !inside => return border.colour,
+        _ => (x.clamp(0, width - 1), y.clamp(0, height - 1)),
+    };
+    img.get_pixel(x as u32, y as u32).0
+}
+
+/// Halve an image by averaging 2x2 blocks, as the mipmap stage does. Colours are
+/// weighted by their alpha, so transparent pixels do not contribute their colour.
+/// An odd row or column is dropped and an image one pixel wide repeats its edge
+pub fn downsample(img: &Rgba32FImage) -> Rgba32FImage {
+    let (width, height) = img.dimensions();
+    let dims = (u32::max(1, width / 2), u32::max(1, height / 2));
+    generate(dims, |x, y| {
+        let p = |dx: u32, dy: u32| {
+            img.get_pixel((x * 2 + dx).min(width - 1), (y * 2 + dy).min(height - 1))
+                .0
+        };
+        let block = [p(0, 0), p(1, 0), p(0, 1), p(1, 1)];
+        let alpha: f32 = block.iter().map(|p| p[3]).sum();
+        match alpha <= 0.0 {
+            true => [0.0; 4],
+            false => {
+                let colour = |i: usize| block.iter().map(|p| p[i] * p[3]).sum::<f32>() / alpha;
+                [colour(0), colour(1), colour(2), alpha * 0.25]
+            }
+        }
+    })
+}
+
+/// Generate the mip chain of an image, starting with the image itself and
+/// halving until a single pixel is left. There are always at least two levels
+pub fn mip_chain(img: Rgba32FImage) -> Vec<Rgba32FImage> {
+    let mut levels = vec![img];
+    while levels.len() < 2 || levels[levels.len() - 1].dimensions() != (1, 1) {
+        let next = downsample(&levels[levels.len() - 1]);
+        levels.push(next);
+    }
+    levels
+}
+
+/// Level of detail for a scale, clamped to the levels available.
+/// A single level is never mipmapped
+fn level_of_detail(scale: f32, levels: usize) -> f32 {
+    (-scale.log2()).clamp(0.0, (levels - 1) as f32)
+}
+
+/// Transform the centre of an output pixel into a level of the mip chain
+fn level_coord(
+    levels: &[Rgba32FImage],
+    level: usize,
+    (x, y): (u32, u32),
+    scale: f32,
+) -> (f32, f32) {
+    let (width, height) = levels[0].dimensions();
+    let (level_width, level_height) = levels[level].dimensions();
+    (
+        (x as f32 + 0.5) / scale * level_width as f32 / width as f32,
+        (y as f32 + 0.5) / scale * level_height as f32 / height as f32,
+    )
+}
+
+/// Perform nearest neighbour interpolation to the target resolution,
+/// from the nearest level of the mip chain
+pub fn nearest_neighbour(
+    levels: &[Rgba32FImage],
+    dims: (u32, u32),
+    scale: f32,
+    border: Border,
+) -> Rgba32FImage {
+    let level = level_of_detail(scale, levels.len()).round_ties_even() as usize;
+    generate(dims, |x, y| {
+        let (transformed_x, transformed_y) = level_coord(levels, level, (x, y), scale);
+        sample_pixel(
+            &levels[level],
+            transformed_x as i32,
+            transformed_y as i32,
+            border,
+        )
+    })
+}
+
+/// Perform bilinear interpolation at a coordinate of an image
+fn bilinear_at(img: &Rgba32FImage, (x, y): (f32, f32), border: Border) -> [f32; 4] {
+    let (transformed_x, transformed_y) = (x - 0.5, y - 0.5);
+    let (left, top) = (transformed_x.floor(), transformed_y.floor());
+    let (dx, dy) = (transformed_x - left, transformed_y - top);
+
+    // Sample all coordinates
+    let top_left = sample_pixel(img, left as i32, top as i32, border);
+    let top_right = sample_pixel(img, left as i32 + 1, top as i32, border);
+    let bottom_left = sample_pixel(img, left as i32, top as i32 + 1, border);
+    let bottom_right = sample_pixel(img, left as i32 + 1, top as i32 + 1, border);
+
+    // Interpolate horizontally then vertically
+    std::array::from_fn(|i| {
+        let top_middle = (1.0 - dx) * top_left[i] + dx * top_right[i];
+        let bottom_middle = (1.0 - dx) * bottom_left[i] + dx * bottom_right[i];
+        (1.0 - dy) * top_middle + dy * bottom_middle
+    })
+}
+
+/// Perform bilinear interpolation to the target resolution, blending
+/// between the two closest levels of the mip chain
+pub fn bilinear(
+    levels: &[Rgba32FImage],
+    dims: (u32, u32),
+    scale: f32,
+    border: Border,
+) -> Rgba32FImage {
+    let lod = level_of_detail(scale, levels.len());
+    let level = lod.floor() as usize;
+    let blend = lod - level as f32;
+    generate(dims, |x, y| {
+        let sample = |level| {
+            bilinear_at(
+                &levels[level],
+                level_coord(levels, level, (x, y), scale),
+                border,
+            )
+        };
+        let upper = sample(level);
+        match blend == 0.0 {
+            true => upper,
+            false => {
+                let lower = sample(level + 1);
+                std::array::from_fn(|i| upper[i] * (1.0 - blend) + lower[i] * blend)
+            }
+        }
+    })
+}
+
+/// Normalized sinc function
+fn sinc(x: f32) -> f32 {
+    match x == 0.0 {
+        true => 1.0,
+        false => (PI * x).sin() / (PI * x),
+    }
+}
+
+/// Cubic filter with the parameters B and C from Mitchell and Netravali
+fn cubic(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+    if x < 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x < 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// Lanczos filter with a lobes
+fn lanczos(x: f32, a: f32) -> f32 {
+    match x.abs() < a {
+        true => sinc(x) * sinc(x / a),
+        false => 0.0,
+    }
+}
+
+/// Weight of a row or column of pixels for a centre in image coordinates. The box
+/// filter takes the overlap of the pixel with the area covered by the output pixel,
+/// other filters are stretched when downscaling to filter out high frequencies
+fn axis_weight(mode: ScalingMode, pixel: i32, centre: f32, scale: f32) -> f32 {
+    let stretch = (1.0 / scale).max(1.0);
+    let x = (pixel as f32 + 0.5 - centre) / stretch;
+    match mode {
+        ScalingMode::Bicubic => cubic(x, 0.0, 0.5),
+        ScalingMode::Mitchell => cubic(x, 1.0 / 3.0, 1.0 / 3.0),
+        ScalingMode::Lanczos2 => lanczos(x, 2.0),
+        ScalingMode::Lanczos3 => lanczos(x, 3.0),
+        ScalingMode::Box => {
+            let half = 0.5 / scale;
+            let overlap =
+                (pixel as f32 + 1.0).min(centre + half) - (pixel as f32).max(centre - half);
+            overlap.max(0.0)
+        }
+        ScalingMode::NearestNeighbour | ScalingMode::Bilinear => unreachable!(),
+    }
+}
+
+/// Distance from the centre to the edge of the filter in image pixels
+fn support_radius(mode: ScalingMode, scale: f32) -> f32 {
+    let support = match mode {
+        ScalingMode::Box => return 0.5 / scale,
+        ScalingMode::Lanczos3 => 3.0,
+        _ => 2.0,
+    };
+    support * (1.0 / scale).max(1.0)
+}
+
+/// Resample to the target resolution with the filter of a scaling mode,
+/// normalizing by the total weight as the filters do not always sum to one
+pub fn resample(
+    img: &Rgba32FImage,
+    dims: (u32, u32),
+    scale: f32,
+    mode: ScalingMode,
+    border: Border,
+) -> Rgba32FImage {
+    let radius = support_radius(mode, scale);
+    generate(dims, |x, y| {
+        let centre = ((x as f32 + 0.5) / scale, (y as f32 + 0.5) / scale);
+        let (first_x, last_x) = (
+            (centre.0 - radius).floor() as i32,
+            (centre.0 + radius).ceil() as i32,
+        );
+        let (first_y, last_y) = (
+            (centre.1 - radius).floor() as i32,
+            (centre.1 + radius).ceil() as i32,
+        );
+
+        let (mut sum, mut total) = ([0.0; 4], 0.0);
+        for sy in first_y..=last_y {
+            let weight_y = axis_weight(mode, sy, centre.1, scale);
+            if weight_y == 0.0 {
+                continue;
+            }
+            for sx in first_x..=last_x {
+                let weight = axis_weight(mode, sx, centre.0, scale) * weight_y;
+                let p = sample_pixel(img, sx, sy, border);
+                for i in 0..4 {
+                    sum[i] += p[i] * weight;
+                }
+                total += weight;
+            }
+        }
+        sum.map(|s| s / total)
+    })
+}
+
+/// Generate the gamma lookup table
+pub fn gamma_lut(gamma: f32) -> [f32; 256] {
+    std::array::from_fn(|i| ((i as f32 + 0.5) / 256.0).powf(1.0 / gamma))
+}
+
+/// Apply gamma correction using the lookup table, alpha is only corrected when processed
+pub fn gamma_correction(img: &Rgba32FImage, lut: &[f32; 256], process_alpha: bool) -> Rgba32FImage {
+    let lookup = |c: f32| lut[((c * 256.0).round_ties_even() as i32).clamp(0, 255) as usize];
+    generate(img.dimensions(), |x, y| {
+        let p = img.get_pixel(x, y).0;
+        let alpha = match process_alpha {
+            true => lookup(p[3]),
+            false => p[3],
+        };
+        [lookup(p[0]), lookup(p[1]), lookup(p[2]), alpha]
+    })
+}
+
+/// Apply gamma correction exactly, as the gamma stage does for 16-bit and float
+/// sources. Values outside of 0..1 keep their sign
+pub fn exact_gamma_correction(img: &Rgba32FImage, gamma: f32, process_alpha: bool) -> Rgba32FImage {
+    let correct = |c: f32| c.signum() * c.abs().powf(1.0 / gamma);
+    generate(img.dimensions(), |x, y| {
+        let p = img.get_pixel(x, y).0;
+        let alpha = match process_alpha {
+            true => correct(p[3]),
+            false => p[3],
+        };
+        [correct(p[0]), correct(p[1]), correct(p[2]), alpha]
+    })
+}
+
+/// Apply the kernel to every pixel, storing the signed sums as the kernel stage does.
+/// The valid border mode crops the result to pixels whose kernel lies inside the image.
+/// Unless alpha is processed, the alpha of the centre pixel is kept
+pub fn apply_kernel(
+    img: &Rgba32FImage,
+    kernel: &Kernel,
+    border: Border,
+    process_alpha: bool,
+) -> Rgba32FImage {
+    let (weights, (width, _)) = (kernel.weights(), kernel.size());
+    let (rx, ry) = kernel.radius();
+    let crop = border.mode.crop(kernel.size());
+    let dims = (
+        img.width().saturating_sub(crop.0).max(1),
+        img.height().saturating_sub(crop.1).max(1),
+    );
+    let offset = match border.mode {
+        BorderMode::Valid => (rx, ry),
+        _ => (0, 0),
+    };
+    generate(dims, |x, y| {
+        let (x, y) = (x as i32 + offset.0, y as i32 + offset.1);
+        let mut s = [0.0; 4];
+        for dy in -ry..=ry {
+            for dx in -rx..=rx {
+                let p = sample_pixel(img, x + dx, y + dy, border);
+                let w = weights[((dy + ry) * width as i32 + dx + rx) as usize];
+                for i in 0..4 {
+                    s[i] += p[i] * w;
+                }
+            }
+        }
+        if !process_alpha {
+            s[3] = sample_pixel(img, x, y, border)[3];
+        }
+        s
+    })
+}
+
+/// Find the min and max over the colour channels of every pixel of a
+/// kernelled image
+pub fn min_max(img: &Rgba32FImage) -> (f32, f32) {
+    let (mini, maxi) = img
+        .pixels()
+        .flat_map(|p| &p.0[..3])
+        .fold((f32::MAX, -f32::MAX), |(mini, maxi), c| {
+            (mini.min(*c), maxi.max(*c))
+        });
+    (mini, maxi)
+}
+
+/// Normalize a kernelled image between the min and max of its colour channels,
+/// a flat image has no range so maps to 0.0. Alpha is only normalized when processed
+pub fn normalize(
+    img: &Rgba32FImage,
+    (mini, maxi): (f32, f32),
+    process_alpha: bool,
+) -> Rgba32FImage {
+    let range = maxi - mini;
+    generate(img.dimensions(), |x, y| {
+        let p = img.get_pixel(x, y).0;
+        let n = |c: f32| match range <= 0.0 {
+            true => 0.0,
+            false => (c - mini) / range,
+        };
+        let alpha = match process_alpha {
+            true => n(p[3]),
+            false => p[3],
+        };
+        [n(p[0]), n(p[1]), n(p[2]), alpha]
+    })
+}
+
+/// Create an image by evaluating a function for every pixel,
+/// splitting rows between threads where available
+fn generate((width, height): (u32, u32), f: impl Fn(u32, u32) -> [f32; 4] + Sync) -> Rgba32FImage {
+    let mut data = vec![0.0; width as usize * height as usize * 4];
+    let fill = |offset: usize, chunk: &mut [f32]| {
+        for (i, pixel) in chunk.chunks_exact_mut(4).enumerate() {
+            let index = offset + i;
+            let (x, y) = (index % width as usize, index / width as usize);
+            pixel.copy_from_slice(&f(x as u32, y as u32));
+        }
+    };
+
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            fill(0, &mut data);
+        } else {
+            let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+            let rows = (height as usize).div_ceil(threads).max(1);
+            let pixels_per_chunk = rows * width as usize;
+            std::thread::scope(|scope| {
+                for (i, chunk) in data.chunks_mut(pixels_per_chunk * 4).enumerate() {
+                    let fill = &fill;
+                    scope.spawn(move || fill(i * pixels_per_chunk, chunk));
+                }
+            });
+        }
+    }
+
+    Rgba32FImage::from_raw(width, height, data).unwrap()
+}
@@ -0,0 +1,159 @@
+use std::{io::Cursor, path::Path};
+
+use anyhow::{anyhow, Result};
+use image::{
+    codecs::{
+        jpeg::JpegEncoder, openexr::OpenExrEncoder, png::PngEncoder, tiff::TiffEncoder,
+        webp::WebPEncoder,
+    },
+    DynamicImage, ImageEncoder,
+};
+
+use crate::colour;
+
+/// Every format an image can be exported as
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub enum ExportFormat {
+    #[default]
+    Png8,
+    Png16,
+    Jpeg {
+        quality: u8,
+    },
+    Tiff8,
+    Tiff16,
+    /// 32-bit float, written from the processed image like the other formats.
+    /// Kernels are normalized to 0..1 in the pipeline, so their signed output is
+    /// not kept, only sources reaching beyond 0..1 are
+    OpenExr,
+    WebP,
+}
+
+impl ExportFormat {
+    /// All export formats with default settings, used for selection in the ui
+    pub const ALL: [ExportFormat; 7] = [
+        ExportFormat::Png8,
+        ExportFormat::Png16,
+        ExportFormat::Jpeg { quality: 90 },
+        ExportFormat::Tiff8,
+        ExportFormat::Tiff16,
+        ExportFormat::OpenExr,
+        ExportFormat::WebP,
+    ];
+
+    /// Guess the export format from the extension of a path
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => Some(Self::Png8),
+            "jpg" | "jpeg" => Some(Self::Jpeg { quality: 90 }),
+            "tif" | "tiff" => Some(Self::Tiff8),
+            "exr" => Some(Self::OpenExr),
+            "webp" => Some(Self::WebP),
+            _ => None,
+        }
+    }
+
+    /// File extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png8 | Self::Png16 => "png",
+            Self::Jpeg { .. } => "jpg",
+            Self::Tiff8 | Self::Tiff16 => "tiff",
+            Self::OpenExr => "exr",
+            Self::WebP => "webp",
+        }
+    }
+
+    /// Human readable name of the format
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Png8 => "PNG (8-bit)",
+            Self::Png16 => "PNG (16-bit)",
+            Self::Jpeg { .. } => "JPEG",
+            Self::Tiff8 => "TIFF (8-bit)",
+            Self::Tiff16 => "TIFF (16-bit)",
+            Self::OpenExr => "OpenEXR (32-bit float)",
+            Self::WebP => "WebP (lossless)",
+        }
+    }
+
+    /// Encode a linear image into the format, float formats keep values outside of 0..1
+    /// in the image, which only come from the source as kernels are normalized.
+    /// Integer formats store the colour encoded with the sRGB transfer curve
+    pub fn encode(&self, img: &DynamicImage) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let (width, height) = (img.width(), img.height());
+        let srgb;
+        let img = match self {
+            Self::OpenExr => img,
+            _ => {
+                srgb = srgb_encoded(img);
+                &srgb
+            }
+        };
+        match *self {
+            Self::Png8 => PngEncoder::new(&mut bytes).write_image(
+                &img.to_rgba8(),
+                width,
+                height,
+                image::ColorType::Rgba8,
+            )?,
+            Self::Png16 => PngEncoder::new(&mut bytes).write_image(
+                bytemuck::cast_slice(&img.to_rgba16()),
+                width,
+                height,
+                image::ColorType::Rgba16,
+            )?,
+            // Jpeg has no alpha channel
+            Self::Jpeg { quality } => JpegEncoder::new_with_quality(&mut bytes, quality)
+                .write_image(&img.to_rgb8(), width, height, image::ColorType::Rgb8)?,
+            Self::Tiff8 => TiffEncoder::new(Cursor::new(&mut bytes)).write_image(
+                &img.to_rgba8(),
+                width,
+                height,
+                image::ColorType::Rgba8,
+            )?,
+            Self::Tiff16 => TiffEncoder::new(Cursor::new(&mut bytes)).write_image(
+                bytemuck::cast_slice(&img.to_rgba16()),
+                width,
+                height,
+                image::ColorType::Rgba16,
+            )?,
+            Self::OpenExr => OpenExrEncoder::new(Cursor::new(&mut bytes)).write_image(
+                bytemuck::cast_slice(&img.to_rgba32f()),
+                width,
+                height,
+                image::ColorType::Rgba32F,
+            )?,
+            Self::WebP => WebPEncoder::new_lossless(&mut bytes).write_image(
+                &img.to_rgba8(),
+                width,
+                height,
+                image::ColorType::Rgba8,
+            )?,
+        }
+        Ok(bytes)
+    }
+}
+
+/// Encode the colour channels of a linear image with the sRGB transfer curve,
+/// alpha is unchanged
+fn srgb_encoded(img: &DynamicImage) -> DynamicImage {
+    let mut img = img.to_rgba32f();
+    for pixel in img.pixels_mut() {
+        for c in &mut pixel.0[..3] {
+            *c = colour::encode(*c);
+        }
+    }
+    DynamicImage::ImageRgba32F(img)
+}
+
+/// Save an image to disk, guessing the format from the path if none is given
+pub fn save_image(img: &DynamicImage, path: &Path, format: Option<ExportFormat>) -> Result<()> {
+    let format = format
+        .or_else(|| ExportFormat::from_path(path))
+        .ok_or_else(|| anyhow!("Unknown export format for {}", path.display()))?;
+    std::fs::write(path, format.encode(img)?)?;
+    Ok(())
+}
@@ -0,0 +1,136 @@
+use crate::{
+    kernel::Kernel,
+    plugins::{Param, Plugin},
+};
+
+/// Filter run on the image between the interpolation and the conversion back
+/// to linear sRGB, with its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Cross correlation with the kernel, normalized by the min and max of the result
+    Kernel(Kernel),
+    /// Gamma correction with the gamma of the display
+    Gamma,
+    /// Filter plugin by file name, with the values of its parameters
+    Plugin { name: String, params: Vec<Param> },
+}
+
+/// Filter in the graph, which can be disabled without losing its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterNode {
+    pub filter: Filter,
+    pub enabled: bool,
+}
+
+/// Ordered list of filters, each reading the result of the enabled filter before it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterGraph {
+    pub nodes: Vec<FilterNode>,
+}
+
+impl Filter {
+    /// Create a plugin filter with the default values of its parameters
+    pub fn plugin(plugin: &Plugin) -> Self {
+        Self::Plugin {
+            name: plugin.name.clone(),
+            params: plugin.params.clone(),
+        }
+    }
+
+    /// Human readable name of the filter
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Kernel(_) => "Cross Correlation",
+            Self::Gamma => "Gamma Correction",
+            Self::Plugin { name, .. } => name,
+        }
+    }
+}
+
+impl FilterNode {
+    pub fn new(filter: Filter, enabled: bool) -> Self {
+        Self { filter, enabled }
+    }
+}
+
+impl FilterGraph {
+    /// The standard chain, cross correlation with the kernel if there is one
+    /// followed by gamma correction
+    pub fn standard(kernel: Option<Kernel>) -> Self {
+        Self {
+            nodes: vec![
+                FilterNode::new(
+                    Filter::Kernel(kernel.clone().unwrap_or_default()),
+                    kernel.is_some(),
+                ),
+                FilterNode::new(Filter::Gamma, true),
+            ],
+        }
+    }
+
+    /// Filters which are enabled, in the order they run
+    pub fn enabled(&self) -> impl Iterator<Item = &Filter> {
+        self.nodes
+            .iter()
+            .filter(|node| node.enabled)
+            .map(|node| &node.filter)
+    }
+
+    /// Kernels of the enabled cross correlations, in the order they run
+    pub fn kernels(&self) -> impl Iterator<Item = &Kernel> {
+        self.enabled().filter_map(|filter| match filter {
+            Filter::Kernel(kernel) => Some(kernel),
+            _ => None,
+        })
+    }
+
+    /// Swap a node with the one after it
+    pub fn move_down(&mut self, index: usize) {
+        if index + 1 < self.nodes.len() {
+            self.nodes.swap(index, index + 1);
+        }
+    }
+
+    /// Swap a node with the one before it
+    pub fn move_up(&mut self, index: usize) {
+        if index > 0 {
+            self.nodes.swap(index - 1, index);
+        }
+    }
+
+    /// Remove a node from the graph
+    pub fn remove(&mut self, index: usize) {
+        self.nodes.remove(index);
+    }
+
+    /// Insert a copy of a node after it, such as a second kernel in series
+    pub fn duplicate(&mut self, index: usize) {
+        let node = self.nodes[index].clone();
+        self.nodes.insert(index + 1, node);
+    }
+
+    /// Update the parameters of every node of a reloaded plugin, keeping the
+    /// values of parameters with the same name and kind
+    pub fn update_plugin(&mut self, plugin: &Plugin) {
+        for node in &mut self.nodes {
+            if let Filter::Plugin { name, params } = &mut node.filter {
+                if *name != plugin.name {
+                    continue;
+                }
+                let old = std::mem::replace(params, plugin.params.clone());
+                for param in params {
+                    let old = old.iter().find(|old| old.name == param.name);
+                    if let Some(old) = old.filter(|old| old.kind == param.kind) {
+                        param.value = old.value;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Default for FilterGraph {
+    fn default() -> Self {
+        Self::standard(None)
+    }
+}
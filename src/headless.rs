@@ -0,0 +1,928 @@
+use std::{borrow::Cow, iter, mem, path::Path};
+
+use anyhow::{anyhow, Ok, Result};
+use image::{DynamicImage, EncodableLayout, Rgba32FImage};
+use wgpu::{util::DeviceExt, CommandEncoder};
+
+use crate::{
+    export::{save_image, ExportFormat},
+    graph::{Filter, FilterGraph, FilterNode},
+    image_display::{BorderMode, ImageDisplay, ImageDisplayWithBuffers, RawImageDisplay},
+    kernel::Kernel,
+    pipelines::{Binding, MinMaxPipelines, Pipelines, Stage},
+    plugins::{self, Plugin},
+    region::{Region, Regions},
+    shaders::{self, Diagnostic},
+    stages::{
+        GlobalRange, KernelStage, MinMaxPasses, MinMaxStage, NodeStage, RenderGroup, RenderStages,
+        TiledReadback,
+    },
+    tiles::SourceTiles,
+    vertex::Vertex,
+};
+
+/// Core of the image processing, storing device information and all data
+/// needed to run the stage chain without a window or surface
+pub struct HeadlessContext {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub pipelines: Pipelines,
+    pub stages: RenderStages,
+    /// Stages the ranges of the whole image are found in, apart from the stages
+    /// of the frame shown
+    pub range_stages: RenderStages,
+    pub buffers: (wgpu::Buffer, wgpu::Buffer),
+    pub texture_sampler: wgpu::Sampler,
+    pub image_display: ImageDisplayWithBuffers,
+    /// Tiles of the loaded image
+    pub source: SourceTiles,
+    /// Filter plugins loaded from the plugin directory, which can be added to the graph
+    pub plugins: Vec<Plugin>,
+    /// Only process the region of the image visible in the window
+    pub viewport: bool,
+    /// Most passes of a kernel over a region run to find the ranges each processed
+    /// frame, the rest run in the following frames which keep the previous ranges
+    pub ranges_per_frame: usize,
+    /// Largest tile a loaded image is split into and processed in, the largest
+    /// texture the device can read back in a single buffer by default
+    pub max_tile_size: u32,
+    /// Fewest layers of an array texture, as GL creates textures with
+    /// a single layer as 2D textures which cannot be bound as arrays
+    pub min_array_layers: u32,
+}
+
+impl HeadlessContext {
+    /// Vertexes spanning screenspace
+    const VERTICES: &'static [Vertex] = &[
+        Vertex::xyz(1.0, 1.0, 0.0),
+        Vertex::xyz(1.0, -1.0, 0.0),
+        Vertex::xyz(-1.0, -1.0, 0.0),
+        Vertex::xyz(-1.0, 1.0, 0.0),
+    ];
+
+    /// Indices for vertexes
+    const INDICES: &'static [u16] = &[0, 3, 1, 1, 3, 2];
+
+    /// Output format used when there is no surface to present to
+    pub const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Create a new HeadlessContext, requesting an adapter without a surface
+    pub async fn new(force_fallback_adapter: bool) -> Result<Self> {
+        // Create a new backend instance
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+
+        // Create a new device adapter, optionally the software fallback
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await
+            .ok_or_else(|| anyhow!("No suitable graphics adapter found"))?;
+
+        HeadlessContext::from_adapter(
+            &adapter,
+            HeadlessContext::OFFSCREEN_FORMAT,
+            RawImageDisplay::default().window_size,
+        )
+        .await
+    }
+
+    /// Create a new HeadlessContext from an existing adapter, building pipelines
+    /// which output to the given format
+    pub async fn from_adapter(
+        adapter: &wgpu::Adapter,
+        output_format: wgpu::TextureFormat,
+        window_size: [f32; 2],
+    ) -> Result<Self> {
+        // Get the queue and device from the adapter
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    features: wgpu::Features::empty(),
+                    #[cfg(target_arch = "wasm32")]
+                    limits: wgpu::Limits::downlevel_webgl2_defaults(),
+                    #[cfg(not(target_arch = "wasm32"))]
+                    limits: wgpu::Limits::default(),
+                    label: None,
+                },
+                None,
+            )
+            .await?;
+
+        // Create buffers, pipelines, shaders for use within the program
+        let image_display = ImageDisplayWithBuffers::new(&device, window_size);
+        let pipelines = Pipelines::new(
+            &device,
+            output_format,
+            &image_display.layout,
+            HeadlessContext::supports_compute(adapter, &device),
+        )
+        .await?;
+        let texture_sampler = HeadlessContext::create_sampler(&device);
+        let buffers = HeadlessContext::create_buffers(&device);
+        let stages = RenderStages::new();
+
+        // Create empty tiles which will be overwritten after the context is created
+        let max_tile_size = HeadlessContext::default_tile_size(&device.limits());
+        let min_array_layers = match adapter.get_info().backend {
+            wgpu::Backend::Gl => 2,
+            _ => 1,
+        };
+        let source = SourceTiles::new_without_context(
+            &DynamicImage::new_rgba8(1, 1),
+            &device,
+            &queue,
+            &pipelines,
+            max_tile_size,
+            min_array_layers,
+        )?;
+
+        let mut context = Self {
+            device,
+            queue,
+            pipelines,
+            stages,
+            range_stages: RenderStages::new(),
+            buffers,
+            texture_sampler,
+            image_display,
+            source,
+            plugins: Vec::new(),
+            viewport: false,
+            ranges_per_frame: usize::MAX,
+            max_tile_size,
+            min_array_layers,
+        };
+
+        // Load the default image into the empty tiles
+        context.load_texture(include_bytes!("../assets/raytrace.jpg"))?;
+
+        Ok(context)
+    }
+
+    /// Largest square tile within the texture limit whose Rgba32Float pixels fit
+    /// in a single buffer, a multiple of 64 so its rows need no copy padding
+    pub fn default_tile_size(limits: &wgpu::Limits) -> u32 {
+        let readable = (limits.max_buffer_size / 16).isqrt();
+        let size = u64::min(limits.max_texture_dimension_2d as u64, readable);
+        (size / 64 * 64) as u32
+    }
+
+    /// Create vertex and index buffers
+    pub fn create_buffers(device: &wgpu::Device) -> (wgpu::Buffer, wgpu::Buffer) {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("vertex_buf"),
+            contents: bytemuck::cast_slice(HeadlessContext::VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("index_buf"),
+            contents: bytemuck::cast_slice(HeadlessContext::INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        (vertex_buffer, index_buffer)
+    }
+
+    /// Create the sampler used for all textures
+    pub fn create_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        })
+    }
+
+    /// Load a new image into the program from bytes
+    pub fn load_texture(&mut self, bytes: &[u8]) -> Result<()> {
+        let img = image::load_from_memory(bytes)?;
+        self.load_image(&img)
+    }
+
+    /// Load a new image into the program from a decoded image, split into tiles
+    /// if it is larger than the device texture limit. 16-bit and float images
+    /// keep their full precision
+    pub fn load_image(&mut self, img: &image::DynamicImage) -> Result<()> {
+        self.source = SourceTiles::new(self, img)?;
+        self.clear_ranges();
+        self.image_display.set_changed();
+        Ok(())
+    }
+
+    /// Rebuild the pipeline of every stage after a shader has changed, as it may be
+    /// included by any of them. Stages whose shader fails keep their previous
+    /// pipeline, returning the errors without repeating those of shared includes
+    pub async fn reload_shaders(&mut self) -> Vec<Diagnostic> {
+        let mut errors = Vec::new();
+        for stage in Stage::ALL {
+            let result = self
+                .pipelines
+                .reload(&self.device, &self.image_display.layout, stage)
+                .await;
+            if let Err(e) = result {
+                if !errors.contains(&e) {
+                    errors.push(e);
+                }
+            }
+        }
+        self.clear_ranges();
+        self.image_display.set_changed();
+        errors
+    }
+
+    /// Load every `.wgsl` file in the directory as a filter plugin, in order of
+    /// their names. Nodes of reloaded plugins keep the values of unchanged parameters,
+    /// and a plugin which fails keeps its previous pipeline, returning the errors.
+    /// A missing directory has no plugins
+    pub async fn load_plugins(&mut self, dir: &Path) -> Vec<Diagnostic> {
+        let mut names: Vec<String> = match std::fs::read_dir(dir) {
+            Result::Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| name.ends_with(".wgsl"))
+                .collect(),
+            Err(e) => {
+                log::info!("Cannot read the plugin directory {}: {e}", dir.display());
+                Vec::new()
+            }
+        };
+        names.sort();
+
+        let mut previous = mem::take(&mut self.plugins);
+        let mut errors = Vec::new();
+        for name in names {
+            let old = previous
+                .iter()
+                .position(|plugin| plugin.name == name)
+                .map(|i| previous.remove(i));
+            match self.load_plugin(dir, &name).await {
+                Result::Ok(plugin) => {
+                    self.image_display.internal.filters.update_plugin(&plugin);
+                    self.plugins.push(plugin);
+                }
+                Err(e) => {
+                    errors.push(e);
+                    self.plugins.extend(old);
+                }
+            }
+        }
+        if self.plugins.is_empty() {
+            log::info!("No plugins loaded from {}", dir.display());
+        }
+        self.clear_ranges();
+        self.image_display.set_changed();
+        errors
+    }
+
+    /// Load a plugin from the directory, its includes are shared with the stages
+    async fn load_plugin(&self, dir: &Path, name: &str) -> Result<Plugin, Diagnostic> {
+        let shader = shaders::preprocess(name, |file| match file == name {
+            true => std::fs::read_to_string(dir.join(file))
+                .map(Cow::Owned)
+                .map_err(|e| anyhow!("Failed to read plugin: {e}")),
+            false => shaders::source(file),
+        })?;
+        let pipeline = self
+            .pipelines
+            .create_plugin(&self.device, &self.image_display.layout, &shader)
+            .await?;
+        let params = plugins::reflect(&shader)?;
+        Result::Ok(Plugin::new(name, pipeline, params))
+    }
+
+    /// Load a new kernel texture, the texture must be the same size as the kernel
+    pub fn write_kernel_texture(queue: &wgpu::Queue, texture: &wgpu::Texture, kernel: &Kernel) {
+        let mut values = Vec::new();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            {
+                // Weights are stored as raw floats in the red channel
+                for i in kernel.weights() {
+                    values.extend_from_slice(&[*i, 0.0, 0.0, 0.0]);
+                }
+                values.as_bytes()
+            },
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * 4 * kernel.size().0),
+                rows_per_image: Some(kernel.size().1),
+            },
+            texture.size(),
+        );
+    }
+
+    pub fn image_display(&self) -> &ImageDisplay {
+        &self.image_display.internal
+    }
+
+    pub fn image_display_mut(&mut self) -> &mut ImageDisplay {
+        &mut self.image_display.internal
+    }
+
+    /// Get the target resolution of the interpolation
+    pub fn scaled_texture_size(&self) -> (u32, u32) {
+        self.image_display().scaled_size(self.source.size())
+    }
+
+    /// Loaded plugin with the file name
+    pub fn plugin(&self, name: &str) -> Option<&Plugin> {
+        self.plugins.iter().find(|plugin| plugin.name == name)
+    }
+
+    /// Enabled filters of the graph which can run, in order. Nodes of plugins
+    /// which are not loaded are skipped
+    pub fn filters(&self) -> impl Iterator<Item = &Filter> {
+        self.image_display()
+            .filters
+            .enabled()
+            .filter(|filter| match filter {
+                Filter::Plugin { name, .. } => self.plugin(name).is_some(),
+                _ => true,
+            })
+    }
+
+    /// Regions of the image processed by the stages, the viewport when only it is
+    /// processed and otherwise the first tile of the whole image. The radius of
+    /// each kernel is the apron around the region of the kernel after it
+    pub fn regions(&self) -> Regions {
+        let mut regions = match self.viewport {
+            true => self.regions_of(|output| vec![self.image_display().visible_region(output)]),
+            false => self.tiles(),
+        };
+        regions.remove(0)
+    }
+
+    /// Regions of each tile the whole image is processed in, in rows. No stage of a
+    /// tile is larger than the largest tile, as the tiles are shrunk by the radius
+    /// of every kernel which they overlap by
+    pub fn tiles(&self) -> Vec<Regions> {
+        let limit = self.max_tile_size;
+        let kernels: Vec<_> = self.image_display().filters.kernels().collect();
+        let apron = kernels.iter().fold((0, 0), |(x, y), kernel| {
+            let size = kernel.size();
+            (x + size.0 / 2, y + size.1 / 2)
+        });
+        let size = (
+            limit.saturating_sub(2 * apron.0).max(1),
+            limit.saturating_sub(2 * apron.1).max(1),
+        );
+        self.regions_of(|(width, height)| {
+            let mut tiles = Vec::new();
+            for y in (0..height).step_by(size.1 as usize) {
+                for x in (0..width).step_by(size.0 as usize) {
+                    tiles.push(Region {
+                        origin: (x as i32, y as i32),
+                        size: (size.0.min(width - x), size.1.min(height - y)),
+                    });
+                }
+            }
+            tiles
+        })
+    }
+
+    /// Regions for each visible region of the output, given the size of the output
+    fn regions_of(&self, visible: impl FnOnce((u32, u32)) -> Vec<Region>) -> Vec<Regions> {
+        let display = self.image_display();
+        let kernels: Vec<_> = display
+            .filters
+            .kernels()
+            .map(|kernel| {
+                let size = kernel.size();
+                (display.border_mode.crop(size), (size.0 / 2, size.1 / 2))
+            })
+            .collect();
+        let scaled = self.scaled_texture_size();
+        let output = kernels
+            .iter()
+            .fold(scaled, |size, &(crop, _)| Regions::output_size(size, crop));
+        let wrap = display.border_mode == BorderMode::Wrap;
+        visible(output)
+            .into_iter()
+            .map(|visible| Regions::new(scaled, &kernels, visible, wrap))
+            .collect()
+    }
+
+    /// Get the max scale the window can zoom to given the original size and the
+    /// device limits, and never less than the original size as larger images are
+    /// processed in tiles
+    pub fn max_scale(&self) -> f32 {
+        let size = self.source.size();
+        let base_size = u32::max(size.0, size.1) as f32;
+        let max_size = self.device.limits().max_texture_dimension_2d as f32;
+        f32::max(max_size / base_size, 1.0)
+    }
+
+    /// Run every processing stage and submit the work, leaving the result in
+    /// the processed stage
+    pub fn render(&mut self) -> &RenderGroup {
+        self.image_display.bind(self);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Headless Encoder"),
+            });
+
+        self.process(&mut encoder);
+
+        self.queue.submit(iter::once(encoder.finish()));
+
+        self.stages.processed()
+    }
+
+    /// Read the result of a stage back from the GPU, waiting for all
+    /// submitted work to finish
+    pub async fn read_back(&self, group: &RenderGroup) -> Result<Rgba32FImage> {
+        group.read(&self.device, &self.queue).await
+    }
+
+    /// Process the whole image tile by tile, copying each tile back to the CPU to
+    /// be stitched together. The stages are left with the last tile
+    pub fn start_read_output(&mut self) -> Result<TiledReadback> {
+        let viewport = mem::replace(&mut self.viewport, false);
+        self.image_display.bind(self);
+        let tiles = self.tiles();
+        let output = tiles[0].output;
+
+        // Tiles are normalized with the min and max of the whole image
+        let kernels = tiles[0].kernels.len();
+        let global = match tiles.len() > 1 && kernels > 0 {
+            true => {
+                self.update_ranges(usize::MAX);
+                kernels
+            }
+            false => 0,
+        };
+
+        let mut readback = TiledReadback::new(output);
+        for regions in tiles {
+            let origin = regions.visible.origin;
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Tile Encoder"),
+                });
+            self.record(&mut encoder, regions, global);
+            self.queue.submit(iter::once(encoder.finish()));
+            let tile = self
+                .stages
+                .processed()
+                .start_read(&self.device, &self.queue)?;
+            readback.push(&self.device, (origin.0 as u32, origin.1 as u32), tile)?;
+        }
+        self.viewport = viewport;
+        Ok(readback)
+    }
+
+    /// Process the whole image and read it back from the GPU, waiting for all
+    /// submitted work to finish
+    pub async fn read_output(&mut self) -> Result<DynamicImage> {
+        let pending = self.start_read_output()?.map();
+        self.device.poll(wgpu::Maintain::Wait);
+        Ok(DynamicImage::ImageRgba32F(pending.await?))
+    }
+
+    /// Process the whole image and save it to disk at its processed resolution,
+    /// guessing the format from the path if none is given
+    pub async fn save_output(&mut self, path: &Path, format: Option<ExportFormat>) -> Result<()> {
+        save_image(&self.read_output().await?, path, format)
+    }
+
+    /// Record every processing stage into the encoder, the stages are only
+    /// recomputed when the ImageDisplay or the processed regions have changed.
+    /// A viewport or tile is normalized with the min and max of the whole image,
+    /// so it matches the saved image and keeps its contrast while panning. Finding
+    /// them is spread over frames, which keep the previous ranges until then
+    pub fn process(&mut self, encoder: &mut CommandEncoder) {
+        let regions = self.regions();
+        let whole = regions.visible == Region::whole(regions.output);
+        let global = !whole && !regions.kernels.is_empty();
+        let mut changed = self.image_display.changed
+            || regions != *self.stages.regions()
+            || self.image_display() != self.stages.display();
+
+        // Ranges still being found are continued each frame, redrawing once found
+        if global && (changed || self.range_stages.has_ranges()) {
+            changed |= self.update_ranges(self.ranges_per_frame);
+        }
+        if !changed {
+            return;
+        }
+
+        let global = match global {
+            true => self.stages.ranges_found().len().min(regions.kernels.len()),
+            false => 0,
+        };
+        self.record(encoder, regions, global);
+        self.image_display.clear_changed();
+    }
+
+    /// Display the ranges of the kernels are found with, keeping only the scaling
+    /// and the filters up to the last kernel which change what the kernels output
+    fn ranges_display(&self) -> ImageDisplay {
+        let display = self.image_display();
+        let mut nodes: Vec<_> = self
+            .filters()
+            .map(|filter| FilterNode::new(filter.clone(), true))
+            .collect();
+        let last = nodes
+            .iter()
+            .rposition(|node| matches!(node.filter, Filter::Kernel(_)));
+        nodes.truncate(last.map_or(0, |last| last + 1));
+        let gamma = nodes.iter().any(|node| node.filter == Filter::Gamma);
+        ImageDisplay {
+            size: display.size,
+            gamma: match gamma {
+                true => display.gamma,
+                false => Default::default(),
+            },
+            scaling_mode: display.scaling_mode,
+            filters: FilterGraph { nodes },
+            border_mode: display.border_mode,
+            border_colour: match display.border_mode {
+                BorderMode::Constant => display.border_colour,
+                _ => Default::default(),
+            },
+            mipmaps: display.mipmaps,
+            process_alpha: display.process_alpha,
+            working_space: display.working_space,
+            ..Default::default()
+        }
+    }
+
+    /// Forget the ranges found and being found, as the image, shaders or plugins
+    /// have changed
+    fn clear_ranges(&mut self) {
+        self.stages.clear_ranges();
+        self.range_stages = RenderStages::new();
+    }
+
+    /// Find the min and max of each kernel over the whole image when the display
+    /// has changed, each kernel normalized by the ranges of those before it. The
+    /// ranges are found in their own stages, running at most the given passes of
+    /// a kernel over a region and continuing from there when called again. Every
+    /// pass is submitted before the work being recorded, returning whether the
+    /// ranges were found and replace the previous ranges of the stages
+    fn update_ranges(&mut self, passes: usize) -> bool {
+        let display = self.ranges_display();
+        if self.stages.ranges(&display).is_some() {
+            self.range_stages = RenderStages::new();
+            return false;
+        }
+
+        let regions = self.tiles();
+        let kernels = regions[0].kernels.len();
+        let mut stages = mem::replace(&mut self.stages, mem::take(&mut self.range_stages));
+        if self.stages.ranges(&display).is_none() {
+            let mut range_stages = mem::take(&mut self.stages);
+            range_stages.start_ranges(self, display, kernels, regions.len() as u32);
+            self.stages = range_stages;
+        }
+
+        let start = self.stages.range_passes();
+        let end = (kernels * regions.len()).min(start.saturating_add(passes));
+        for pass in start..end {
+            let (kernel, i) = (pass / regions.len(), pass % regions.len());
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Range Encoder"),
+                });
+            self.record(&mut encoder, regions[i].clone(), kernel);
+
+            // Keep the min and max of the region, reducing them once every
+            // region has been processed
+            let stage = self.stages.kernels().nth(kernel).unwrap();
+            let range = &self.stages.ranges_found()[kernel];
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &stage.min_max.result,
+                    layout: wgpu::ImageDataLayout::default(),
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &range.regions.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: i as u32,
+                        y: 0,
+                        z: 0,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d::default(),
+            );
+            if i + 1 == regions.len() {
+                self.min_max_pass(&mut encoder, &range.regions, &range.min_max);
+            }
+            self.queue.submit(iter::once(encoder.finish()));
+        }
+        self.stages.run_range_passes(end - start);
+
+        let found = end == kernels * regions.len();
+        mem::swap(&mut self.stages, &mut stages);
+        match found {
+            true => self.stages.finish_ranges(stages),
+            false => self.range_stages = stages,
+        }
+        found
+    }
+
+    /// Record every stage for the regions, normalizing the first kernels with
+    /// the min and max of the whole image and the rest with their own
+    fn record(&mut self, encoder: &mut CommandEncoder, regions: Regions, global: usize) {
+        let mut stages = mem::take(&mut self.stages);
+        stages.update_resolution(self, regions);
+        self.stages = stages;
+
+        // Interpolate image
+        self.render_pass(
+            encoder,
+            &self.pipelines.interpolation,
+            &self.stages.output_staging().view,
+            &[
+                Binding(0, &self.source.bind_group),
+                Binding(1, &self.image_display.bind_group),
+                Binding(2, &self.stages.passes().interpolation.bind_group),
+            ],
+            false,
+        );
+
+        // Generate the lookup table
+        self.render_pass(
+            encoder,
+            &self.pipelines.gamma_lut,
+            &self.stages.gamma_lut().view,
+            &[Binding(0, &self.image_display.bind_group)],
+            false,
+        );
+
+        // Run each filter of the graph on the result of the one before it
+        let mut staging = self.stages.output_staging();
+        let mut ranges = self.stages.ranges_found()[..global].iter();
+        for node in self.stages.nodes() {
+            match node {
+                NodeStage::Kernel(stage) => {
+                    self.kernel_pass(encoder, staging, stage, ranges.next())
+                }
+                NodeStage::Gamma(output) => self.render_pass(
+                    encoder,
+                    &self.pipelines.gamma,
+                    &output.view,
+                    &[
+                        Binding(0, &staging.bind_group),
+                        Binding(1, &self.image_display.bind_group),
+                        Binding(2, &self.stages.gamma_lut().bind_group),
+                        Binding(3, &self.source.bind_group),
+                    ],
+                    false,
+                ),
+                NodeStage::Plugin(stage) => {
+                    let plugin = self.plugin(&stage.name).expect("stage of a loaded plugin");
+                    self.render_pass(
+                        encoder,
+                        &plugin.pipeline,
+                        &stage.output.view,
+                        &[
+                            Binding(0, &staging.bind_group),
+                            Binding(1, &self.image_display.bind_group),
+                            Binding(2, &stage.bind_group),
+                            Binding(3, &stage.region.bind_group),
+                        ],
+                        false,
+                    );
+                }
+            }
+            staging = node.output();
+        }
+
+        // Convert the filtered image back to linear sRGB
+        self.render_pass(
+            encoder,
+            &self.pipelines.convert,
+            &self.stages.processed().view,
+            &[
+                Binding(0, &staging.bind_group),
+                Binding(1, &self.image_display.bind_group),
+            ],
+            false,
+        );
+    }
+
+    /// Apply a kernel to the staging image then normalize it, as a horizontal then a
+    /// vertical pass if the kernel is separable. A constant border is not separable
+    /// as the first pass would leave the border colour unfiltered. The range of the
+    /// whole image is used in place of the min and max of the result when given
+    fn kernel_pass(
+        &self,
+        encoder: &mut CommandEncoder,
+        staging: &RenderGroup,
+        stage: &KernelStage,
+        range: Option<&GlobalRange>,
+    ) {
+        let border_mode = self.image_display().border_mode;
+        match &stage.separable_kernel {
+            Some((row, column)) if border_mode != BorderMode::Constant => {
+                self.render_pass(
+                    encoder,
+                    &self.pipelines.kernel,
+                    &stage.separable.view,
+                    &[
+                        Binding(0, &staging.bind_group),
+                        Binding(1, &self.image_display.bind_group),
+                        Binding(2, &row.bind_group),
+                        Binding(3, &stage.passes.row.bind_group),
+                    ],
+                    false,
+                );
+                self.render_pass(
+                    encoder,
+                    &self.pipelines.kernel,
+                    &stage.kerneled.view,
+                    &[
+                        Binding(0, &stage.separable.bind_group),
+                        Binding(1, &self.image_display.bind_group),
+                        Binding(2, &column.bind_group),
+                        Binding(3, &stage.passes.column.bind_group),
+                    ],
+                    false,
+                );
+            }
+            _ => self.render_pass(
+                encoder,
+                &self.pipelines.kernel,
+                &stage.kerneled.view,
+                &[
+                    Binding(0, &staging.bind_group),
+                    Binding(1, &self.image_display.bind_group),
+                    Binding(2, &stage.kernel.bind_group),
+                    Binding(3, &stage.passes.kernel.bind_group),
+                ],
+                false,
+            ),
+        }
+
+        // Get Min Max from the kernelled image
+        match range {
+            Some(range) => encoder.copy_buffer_to_buffer(
+                &range.min_max.result,
+                0,
+                &stage.min_max.result,
+                0,
+                16,
+            ),
+            None => self.min_max_pass(encoder, &stage.kerneled, &stage.min_max),
+        }
+
+        // Normalize the image based on the Min Max found
+        self.render_pass(
+            encoder,
+            &self.pipelines.normalize,
+            &stage.normalized.view,
+            &[
+                Binding(0, &stage.kerneled.bind_group),
+                Binding(1, &self.image_display.bind_group),
+                Binding(2, &stage.min_max.bind_group),
+            ],
+            false,
+        );
+    }
+
+    /// Whether the min max reduction can run as compute passes on the device
+    fn supports_compute(adapter: &wgpu::Adapter, device: &wgpu::Device) -> bool {
+        let limits = device.limits();
+        adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && limits.max_compute_invocations_per_workgroup >= MinMaxStage::WORKGROUP_SIZE
+            && limits.max_compute_workgroup_size_x >= MinMaxStage::WORKGROUP_SIZE
+            && limits.max_storage_buffers_per_shader_stage >= 2
+    }
+
+    /// Reduce a texture to its min and max, leaving the result in the uniform
+    /// buffer of the min max stage created for it
+    fn min_max_pass(
+        &self,
+        encoder: &mut CommandEncoder,
+        source: &RenderGroup,
+        stage: &MinMaxStage,
+    ) {
+        match (&self.pipelines.min_max, &stage.passes) {
+            (
+                MinMaxPipelines::Compute {
+                    reduce_texture,
+                    reduce_buffer,
+                    ..
+                },
+                MinMaxPasses::Compute {
+                    texture_pass: (texture_bind_group, (x, y)),
+                    buffer_passes,
+                    output,
+                },
+            ) => {
+                {
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: Some("Min Max Pass"),
+                            timestamp_writes: None,
+                        });
+                    compute_pass.set_pipeline(reduce_texture);
+                    compute_pass.set_bind_group(0, texture_bind_group, &[]);
+                    compute_pass.dispatch_workgroups(*x, *y, 1);
+
+                    compute_pass.set_pipeline(reduce_buffer);
+                    for (bind_group, groups) in buffer_passes {
+                        compute_pass.set_bind_group(0, bind_group, &[]);
+                        compute_pass.dispatch_workgroups(*groups, 1, 1);
+                    }
+                }
+                // Repeat the min after the max, as the fragment passes store it
+                encoder.copy_buffer_to_buffer(output, 0, &stage.result, 0, 8);
+                encoder.copy_buffer_to_buffer(output, 0, &stage.result, 8, 4);
+            }
+            (MinMaxPipelines::Fragment(pipeline), MinMaxPasses::Fragment(groups)) => {
+                let mut source = source;
+                for group in groups {
+                    self.render_pass(
+                        encoder,
+                        pipeline,
+                        &group.view,
+                        &[Binding(0, &source.bind_group)],
+                        false,
+                    );
+                    source = group;
+                }
+                // The final texel holds the min and max in its first two channels
+                encoder.copy_texture_to_buffer(
+                    source.texture.as_image_copy(),
+                    wgpu::ImageCopyBuffer {
+                        buffer: &stage.result,
+                        layout: wgpu::ImageDataLayout::default(),
+                    },
+                    wgpu::Extent3d::default(),
+                );
+            }
+            _ => unreachable!("min max stage created for different pipelines"),
+        }
+    }
+
+    /// Perform a render pass, given a render target and bind operations
+    pub fn render_pass(
+        &self,
+        encoder: &mut CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        tex_out: &wgpu::TextureView,
+        bindings: &[Binding],
+        clear: bool,
+    ) {
+        // Begin render pass
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: tex_out,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: {
+                        match clear {
+                            true => wgpu::LoadOp::Clear(wgpu::Color {
+                                r: self.image_display().background_colour[0] as f64,
+                                g: self.image_display().background_colour[1] as f64,
+                                b: self.image_display().background_colour[2] as f64,
+                                a: self.image_display().background_colour[3] as f64,
+                            }),
+                            false => wgpu::LoadOp::Load,
+                        }
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+
+        // Bind everything and draw
+        render_pass.set_pipeline(pipeline);
+        for Binding(index, bind_group) in bindings {
+            render_pass.set_bind_group(*index, bind_group, &[])
+        }
+        render_pass.set_vertex_buffer(0, self.buffers.0.slice(..));
+        render_pass.set_index_buffer(self.buffers.1.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..HeadlessContext::INDICES.len() as u32, 0, 0..1);
+    }
+}
@@ -1,6 +1,6 @@
 use wgpu::util::DeviceExt;
 
-use crate::context::GraphicsContext;
+use crate::{colour::WorkingSpace, graph::FilterGraph, headless::HeadlessContext, region::Region};
 
 /// Store ImageDisplay alongside its layout and buffers
 /// Also store change detection
@@ -14,19 +14,30 @@ pub struct ImageDisplayWithBuffers {
 }
 
 /// Data for Image Display
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ImageDisplay {
     pub window_size: [f32; 2],
     pub pos: [f32; 2],
     pub size: f32,
     pub gamma: f32,
     pub scaling_mode: ScalingMode,
-    pub cross_correlation: bool,
     pub background_colour: [f32; 4],
-    pub kernel: [f32; 25],
+    /// Filters run on the interpolated image
+    pub filters: FilterGraph,
+    pub border_mode: BorderMode,
+    /// Linear sRGB colour of the constant border
+    pub border_colour: [f32; 4],
+    /// Sample from the mip chain when downscaling with nearest neighbour or bilinear
+    pub mipmaps: bool,
+    /// Apply the kernel, normalization and gamma to alpha as a colour channel,
+    /// otherwise alpha passes through them unchanged
+    pub process_alpha: bool,
+    /// Colour space the stages operate in
+    pub working_space: WorkingSpace,
 }
 
-/// Raw representation of ImageDisplay for binding to the GPU
+/// Raw representation of ImageDisplay for binding to the GPU, matching the
+/// ImageDisplay struct of uniforms.wgsl
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct RawImageDisplay {
@@ -35,7 +46,12 @@ pub struct RawImageDisplay {
     pub size: f32,
     pub gamma: f32,
     pub scaling_mode: u32,
-    pub _pad: f32,
+    pub border_mode: u32,
+    pub border_colour: [f32; 4],
+    pub mipmaps: u32,
+    pub process_alpha: u32,
+    pub working_space: u32,
+    pub _pad: u32,
 }
 
 /// Scaling Mode Enum
@@ -43,6 +59,30 @@ pub struct RawImageDisplay {
 pub enum ScalingMode {
     NearestNeighbour = 0,
     Bilinear = 1,
+    /// Catmull-Rom cubic
+    Bicubic = 2,
+    /// Mitchell-Netravali cubic with B = C = 1/3
+    Mitchell = 3,
+    Lanczos2 = 4,
+    Lanczos3 = 5,
+    /// Area averaging
+    Box = 6,
+}
+
+/// How pixels outside of the image are sampled by interpolation and convolution
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum BorderMode {
+    /// Repeat the edge pixel
+    Clamp = 0,
+    /// Reflect the image, repeating the edge pixel
+    Mirror = 1,
+    /// Tile the image
+    Wrap = 2,
+    /// Use the border colour
+    Constant = 3,
+    /// Crop the kernelled image to pixels whose kernel lies inside the image,
+    /// interpolation clamps
+    Valid = 4,
 }
 
 impl ImageDisplayWithBuffers {
@@ -51,11 +91,21 @@ impl ImageDisplayWithBuffers {
         device: &wgpu::Device,
         window: &winit::window::Window,
     ) -> ImageDisplayWithBuffers {
-        let mut raw_image_display = RawImageDisplay::default();
-        raw_image_display.window_size = [
-            window.inner_size().width as f32,
-            window.inner_size().height as f32,
-        ];
+        ImageDisplayWithBuffers::new(
+            device,
+            [
+                window.inner_size().width as f32,
+                window.inner_size().height as f32,
+            ],
+        )
+    }
+
+    /// Create a new ImageDisplay for a given window size, with no window required
+    pub fn new(device: &wgpu::Device, window_size: [f32; 2]) -> ImageDisplayWithBuffers {
+        let raw_image_display = RawImageDisplay {
+            window_size,
+            ..Default::default()
+        };
 
         // Create layout entrys
         let entries = (0..=6)
@@ -103,27 +153,12 @@ impl ImageDisplayWithBuffers {
             label: Some("image_display_bind_group"),
         });
 
-        let RawImageDisplay {
-            window_size,
-            pos,
-            size,
-            gamma,
-            scaling_mode,
-            ..
-        } = raw_image_display;
-
         // Return ImageDisplay
         ImageDisplayWithBuffers {
             changed: true,
             internal: ImageDisplay {
                 window_size,
-                pos,
-                size,
-                gamma,
-                scaling_mode: ScalingMode::from_u32(scaling_mode),
-                cross_correlation: false,
-                background_colour: [0.0, 0.0, 0.0, 1.0],
-                kernel: *GraphicsContext::LAPLACIAN,
+                ..Default::default()
             },
             layout,
             buffer,
@@ -132,7 +167,7 @@ impl ImageDisplayWithBuffers {
     }
 
     /// Bind ImageDisplay to the buffer
-    pub fn bind(&self, context: &GraphicsContext) {
+    pub fn bind(&self, context: &HeadlessContext) {
         context.queue.write_buffer(
             &self.buffer,
             0,
@@ -158,7 +193,38 @@ impl ImageDisplay {
             size: self.size,
             gamma: self.gamma,
             scaling_mode: self.scaling_mode as u32,
-            ..Default::default()
+            border_mode: self.border_mode as u32,
+            // The border is sampled in place of pixels already in the working space
+            border_colour: self.working_space.from_linear_srgb(self.border_colour),
+            mipmaps: self.mipmaps as u32,
+            process_alpha: self.process_alpha as u32,
+            working_space: self.working_space as u32,
+            _pad: 0,
+        }
+    }
+
+    /// Get the target resolution of the interpolation for an original size
+    pub fn scaled_size(&self, original_size: (u32, u32)) -> (u32, u32) {
+        (
+            u32::max(1, (original_size.0 as f32 * self.size).floor() as u32),
+            u32::max(1, (original_size.1 as f32 * self.size).floor() as u32),
+        )
+    }
+
+    /// Region of an image of the given size which is visible in the window,
+    /// when centred on the window and offset by the position as the output shader does
+    pub fn visible_region(&self, size: (u32, u32)) -> Region {
+        let axis = |size: u32, window: f32, pos: f32| {
+            let start = size as f32 / 2.0 - window / 2.0 - pos;
+            let first = (start.floor().max(0.0) as u32).min(size - 1);
+            let last = ((start + window).ceil().min(size as f32) as u32).max(first + 1);
+            (first, last - first)
+        };
+        let x = axis(size.0, self.window_size[0], self.pos[0]);
+        let y = axis(size.1, self.window_size[1], self.pos[1]);
+        Region {
+            origin: (x.0 as i32, y.0 as i32),
+            size: (x.1, y.1),
         }
     }
 
@@ -170,6 +236,11 @@ impl ImageDisplay {
             size,
             gamma,
             scaling_mode,
+            border_mode,
+            border_colour,
+            mipmaps,
+            process_alpha,
+            working_space,
             ..
         } = RawImageDisplay::default();
 
@@ -178,9 +249,46 @@ impl ImageDisplay {
         self.size = size;
         self.gamma = gamma;
         self.scaling_mode = ScalingMode::from_u32(scaling_mode);
+        self.border_mode = BorderMode::from_u32(border_mode);
+        self.border_colour = border_colour;
+        self.mipmaps = mipmaps != 0;
+        self.process_alpha = process_alpha != 0;
+        self.working_space = WorkingSpace::from_u32(working_space);
         self.background_colour = [0.0, 0.0, 0.0, 1.0];
-        self.kernel = *GraphicsContext::LAPLACIAN;
-        self.cross_correlation = false;
+        self.filters = FilterGraph::default();
+    }
+}
+
+impl Default for ImageDisplay {
+    fn default() -> Self {
+        let RawImageDisplay {
+            window_size,
+            pos,
+            size,
+            gamma,
+            scaling_mode,
+            border_mode,
+            border_colour,
+            mipmaps,
+            process_alpha,
+            working_space,
+            ..
+        } = RawImageDisplay::default();
+
+        Self {
+            window_size,
+            pos,
+            size,
+            gamma,
+            scaling_mode: ScalingMode::from_u32(scaling_mode),
+            background_colour: [0.0, 0.0, 0.0, 1.0],
+            filters: FilterGraph::default(),
+            border_mode: BorderMode::from_u32(border_mode),
+            border_colour,
+            mipmaps: mipmaps != 0,
+            process_alpha: process_alpha != 0,
+            working_space: WorkingSpace::from_u32(working_space),
+        }
     }
 }
 
@@ -192,17 +300,81 @@ impl Default for RawImageDisplay {
             size: 1.,
             gamma: 1.,
             scaling_mode: 0,
-            _pad: Default::default(),
+            border_mode: 0,
+            border_colour: [0.0, 0.0, 0.0, 1.0],
+            mipmaps: 1,
+            process_alpha: 0,
+            working_space: 0,
+            _pad: 0,
         }
     }
 }
 
 impl ScalingMode {
+    /// Every scaling mode, used for selection in the ui
+    pub const ALL: [ScalingMode; 7] = [
+        ScalingMode::NearestNeighbour,
+        ScalingMode::Bilinear,
+        ScalingMode::Bicubic,
+        ScalingMode::Mitchell,
+        ScalingMode::Lanczos2,
+        ScalingMode::Lanczos3,
+        ScalingMode::Box,
+    ];
+
     pub fn from_u32(i: u32) -> ScalingMode {
         match i {
             0 => Self::NearestNeighbour,
             1 => Self::Bilinear,
+            2 => Self::Bicubic,
+            3 => Self::Mitchell,
+            4 => Self::Lanczos2,
+            5 => Self::Lanczos3,
+            6 => Self::Box,
             _ => panic!(),
         }
     }
+
+    /// Human readable name of the scaling mode
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::NearestNeighbour => "Nearest Neighbour",
+            Self::Bilinear => "Bi-Linear",
+            Self::Bicubic => "Bi-Cubic (Catmull-Rom)",
+            Self::Mitchell => "Mitchell-Netravali",
+            Self::Lanczos2 => "Lanczos-2",
+            Self::Lanczos3 => "Lanczos-3",
+            Self::Box => "Box (Area Average)",
+        }
+    }
+}
+
+impl BorderMode {
+    /// Every border mode, used for selection in the ui
+    pub const ALL: [BorderMode; 5] = [
+        BorderMode::Clamp,
+        BorderMode::Mirror,
+        BorderMode::Wrap,
+        BorderMode::Constant,
+        BorderMode::Valid,
+    ];
+
+    pub fn from_u32(i: u32) -> BorderMode {
+        match i {
+            0 => Self::Clamp,
+            1 => Self::Mirror,
+            2 => Self::Wrap,
+            3 => Self::Constant,
+            4 => Self::Valid,
+            _ => panic!(),
+        }
+    }
+
+    /// Size removed from the kernelled image by a kernel of the given size
+    pub fn crop(&self, kernel_size: (u32, u32)) -> (u32, u32) {
+        match self {
+            BorderMode::Valid => (kernel_size.0 - 1, kernel_size.1 - 1),
+            _ => (0, 0),
+        }
+    }
 }
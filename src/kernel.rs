@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Context, Result};
+
+/// Cross correlation kernel of odd width and height, stored row by row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    size: (u32, u32),
+    weights: Vec<f32>,
+}
+
+impl Kernel {
+    /// Largest width or height of a kernel
+    pub const MAX_SIZE: u32 = 31;
+
+    /// Largest difference from a rank-1 kernel, relative to the largest weight,
+    /// for a kernel to be treated as separable
+    pub const SEPARABLE_TOLERANCE: f32 = 1e-6;
+
+    /// Laplacian matrix
+    pub const LAPLACIAN: [f32; 25] = [
+        -4.0, -1.0, 0.0, -1.0, -4.0, -1.0, 2.0, 3.0, 2.0, -1.0, 0.0, 3.0, 4.0, 3.0, 0.0, -1.0, 2.0,
+        3.0, 2.0, -1.0, -4.0, -1.0, 0.0, -1.0, -4.0,
+    ];
+
+    /// Create a kernel from its size and weights, the size must be odd
+    /// and no larger than MAX_SIZE in either direction
+    pub fn new(size: (u32, u32), weights: Vec<f32>) -> Result<Kernel> {
+        Kernel::check_size(size)?;
+        if weights.len() != (size.0 * size.1) as usize {
+            return Err(anyhow!(
+                "Kernel of size {}x{} needs {} values, found {}",
+                size.0,
+                size.1,
+                size.0 * size.1,
+                weights.len()
+            ));
+        }
+        Ok(Kernel { size, weights })
+    }
+
+    /// The 5x5 laplacian kernel
+    pub fn laplacian() -> Kernel {
+        Kernel {
+            size: (5, 5),
+            weights: Kernel::LAPLACIAN.to_vec(),
+        }
+    }
+
+    /// Parse a kernel from rows separated by semicolons of values separated by
+    /// commas, a single row with a square number of values is taken as square
+    pub fn parse(kernel: &str) -> Result<Kernel> {
+        if kernel.eq_ignore_ascii_case("laplacian") {
+            return Ok(Kernel::laplacian());
+        }
+        let rows = kernel
+            .split(';')
+            .map(|row| {
+                row.split(',')
+                    .map(|v| v.trim().parse::<f32>())
+                    .collect::<Result<Vec<f32>, _>>()
+            })
+            .collect::<Result<Vec<Vec<f32>>, _>>()
+            .context("Kernel values must be numbers")?;
+
+        if rows.iter().any(|row| row.len() != rows[0].len()) {
+            return Err(anyhow!("Every row of the kernel must be the same length"));
+        }
+        let size = match rows.as_slice() {
+            [row] => {
+                let side = (row.len() as f64).sqrt() as u32;
+                match side * side == row.len() as u32 {
+                    true => (side, side),
+                    false => (row.len() as u32, 1),
+                }
+            }
+            rows => (rows[0].len() as u32, rows.len() as u32),
+        };
+        Kernel::new(size, rows.concat())
+    }
+
+    /// Split a rank-1 kernel into a row and a column whose outer product is the
+    /// kernel, pivoting on the largest weight. None if the kernel is not separable
+    pub fn separate(&self) -> Option<(Kernel, Kernel)> {
+        let (width, height) = self.size;
+        let (pivot, &max) = self
+            .weights
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()))?;
+        if max == 0.0 {
+            return None;
+        }
+
+        let (pivot_x, pivot_y) = (pivot as u32 % width, pivot as u32 / width);
+        let row: Vec<f32> = (0..width).map(|x| self.get(x, pivot_y)).collect();
+        let column: Vec<f32> = (0..height).map(|y| self.get(pivot_x, y) / max).collect();
+
+        // Every weight must be the product of its row and column weights
+        let tolerance = max.abs() * Kernel::SEPARABLE_TOLERANCE;
+        let separable = (0..height).all(|y| {
+            (0..width)
+                .all(|x| (self.get(x, y) - column[y as usize] * row[x as usize]).abs() <= tolerance)
+        });
+        let row = Kernel {
+            size: (width, 1),
+            weights: row,
+        };
+        let column = Kernel {
+            size: (1, height),
+            weights: column,
+        };
+        separable.then_some((row, column))
+    }
+
+    /// Check a size is odd and within the limits
+    fn check_size((width, height): (u32, u32)) -> Result<()> {
+        let valid = |n: u32| n % 2 == 1 && n <= Kernel::MAX_SIZE;
+        match valid(width) && valid(height) {
+            true => Ok(()),
+            false => Err(anyhow!(
+                "Kernel size {width}x{height} must be odd and at most {0}x{0}",
+                Kernel::MAX_SIZE
+            )),
+        }
+    }
+
+    /// Resize the kernel, keeping the weights that remain around the centre
+    /// and filling new weights with zero
+    pub fn resize(&mut self, size: (u32, u32)) -> Result<()> {
+        Kernel::check_size(size)?;
+        let offset = (
+            (size.0 as i32 - self.size.0 as i32) / 2,
+            (size.1 as i32 - self.size.1 as i32) / 2,
+        );
+        let mut weights = vec![0.0; (size.0 * size.1) as usize];
+        for y in 0..size.1 as i32 {
+            for x in 0..size.0 as i32 {
+                let (old_x, old_y) = (x - offset.0, y - offset.1);
+                if (0..self.size.0 as i32).contains(&old_x)
+                    && (0..self.size.1 as i32).contains(&old_y)
+                {
+                    weights[(y * size.0 as i32 + x) as usize] =
+                        self.get(old_x as u32, old_y as u32);
+                }
+            }
+        }
+        *self = Kernel { size, weights };
+        Ok(())
+    }
+
+    /// Width and height of the kernel
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Distance from the centre to the edge of the kernel in each direction
+    pub fn radius(&self) -> (i32, i32) {
+        (self.size.0 as i32 / 2, self.size.1 as i32 / 2)
+    }
+
+    /// Weights of the kernel row by row
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// Mutable weights of the kernel row by row
+    pub fn weights_mut(&mut self) -> &mut [f32] {
+        &mut self.weights
+    }
+
+    /// Get the weight at a position, where (0, 0) is the top left
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.weights[(y * self.size.0 + x) as usize]
+    }
+}
+
+impl Default for Kernel {
+    fn default() -> Self {
+        Kernel::laplacian()
+    }
+}
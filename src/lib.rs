@@ -1,6 +1,3 @@
-#![feature(async_closure)]
-#![feature(option_take_if)]
-
 use context::GraphicsContext;
 
 use futures::SinkExt;
@@ -15,12 +12,22 @@ use winit::{
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
+pub mod colour;
 pub mod context;
+pub mod cpu;
+pub mod export;
+pub mod graph;
+pub mod headless;
 pub mod image_display;
 pub mod input;
+pub mod kernel;
 pub mod pipelines;
+pub mod plugins;
+pub mod region;
+pub mod shaders;
 pub mod stages;
 pub mod thread_context;
+pub mod tiles;
 pub mod vertex;
 pub mod window;
 
@@ -65,9 +72,14 @@ pub async fn run() {
     window.run(move |window, event, control_flow| {
         // Load a new image if bytes receieved from the channel
         if let Ok(Some(bytes)) = context.thread.receiver.try_next() {
-            let _ = context.load_texture(bytes.as_bytes());
+            if let Err(e) = context.headless.load_texture(bytes.as_bytes()) {
+                log::error!("Failed to load image: {e}");
+            }
         }
 
+        // Rebuild the pipelines of shaders written to the override directory
+        context.reload_shaders();
+
         // Handle Winit Events
         context.egui.platform.handle_event(&event);
         match event {
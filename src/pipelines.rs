@@ -1,15 +1,148 @@
-use crate::{load_bytes, vertex::Vertex};
+use anyhow::{anyhow, Result};
+
+use crate::{
+    shaders::{self, Diagnostic, Preprocessed},
+    vertex::Vertex,
+};
 
 /// Pipelines created from shaders
 pub struct Pipelines {
     pub bind_group_layouts: TextureBindGroupLayouts,
     pub interpolation: wgpu::RenderPipeline,
+    pub mipmap: wgpu::RenderPipeline,
     pub kernel: wgpu::RenderPipeline,
-    pub min_max: wgpu::RenderPipeline,
+    pub min_max: MinMaxPipelines,
+    pub min_max_layout: wgpu::BindGroupLayout,
+    pub region_layout: wgpu::BindGroupLayout,
+    pub source_layout: wgpu::BindGroupLayout,
+    /// Uniform parameters of a filter plugin
+    pub params_layout: wgpu::BindGroupLayout,
     pub normalize: wgpu::RenderPipeline,
     pub gamma_lut: wgpu::RenderPipeline,
     pub gamma: wgpu::RenderPipeline,
+    pub convert: wgpu::RenderPipeline,
     pub output: wgpu::RenderPipeline,
+    output_format: wgpu::TextureFormat,
+    compute_shaders: bool,
+}
+
+/// Processing stages with a pipeline created from their own shader
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stage {
+    Interpolation,
+    Mipmap,
+    Kernel,
+    MinMax,
+    Normalize,
+    GammaLut,
+    Gamma,
+    Convert,
+    Output,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 9] = [
+        Stage::Interpolation,
+        Stage::Mipmap,
+        Stage::Kernel,
+        Stage::MinMax,
+        Stage::Normalize,
+        Stage::GammaLut,
+        Stage::Gamma,
+        Stage::Convert,
+        Stage::Output,
+    ];
+
+    /// File name of the shader of the stage, the min max reduction depends
+    /// on whether compute shaders are supported
+    pub fn shader(&self, compute_shaders: bool) -> &'static str {
+        match self {
+            Self::Interpolation => "interpolation.wgsl",
+            Self::Mipmap => "mipmap.wgsl",
+            Self::Kernel => "kernel.wgsl",
+            Self::MinMax => match compute_shaders {
+                true => "min_max_reduce.wgsl",
+                false => "min_max.wgsl",
+            },
+            Self::Normalize => "normalize.wgsl",
+            Self::GammaLut => "gamma_lookup.wgsl",
+            Self::Gamma => "gamma_correction.wgsl",
+            Self::Convert => "convert.wgsl",
+            Self::Output => "output.wgsl",
+        }
+    }
+
+    /// Label of the pipeline of the stage
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Interpolation => "interpolation",
+            Self::Mipmap => "mipmap",
+            Self::Kernel => "kernel",
+            Self::MinMax => "min_max",
+            Self::Normalize => "normalize",
+            Self::GammaLut => "gamma_lut",
+            Self::Gamma => "gamma",
+            Self::Convert => "convert",
+            Self::Output => "output",
+        }
+    }
+}
+
+/// Bind group layouts the stage pipelines are created with
+struct StageLayouts<'a> {
+    textures: &'a TextureBindGroupLayouts,
+    image_display: &'a wgpu::BindGroupLayout,
+    region: &'a wgpu::BindGroupLayout,
+    source: &'a wgpu::BindGroupLayout,
+    min_max: &'a wgpu::BindGroupLayout,
+    output_format: wgpu::TextureFormat,
+}
+
+impl StageLayouts<'_> {
+    /// Create the render pipeline of a stage from its shader
+    fn create(
+        &self,
+        device: &wgpu::Device,
+        stage: Stage,
+        shader: wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        let float = &self.textures.rgba32float;
+        let layouts: &[&wgpu::BindGroupLayout] = match stage {
+            Stage::Interpolation => &[self.source, self.image_display, self.region],
+            Stage::Mipmap | Stage::MinMax => &[float],
+            Stage::Kernel => &[float, self.image_display, float, self.region],
+            Stage::Normalize => &[float, self.image_display, self.min_max],
+            Stage::GammaLut => &[self.image_display],
+            // Gamma reads the precision of the source
+            Stage::Gamma => &[float, self.image_display, float, self.source],
+            Stage::Convert => &[float, self.image_display],
+            Stage::Output => &[float, self.image_display, self.region],
+        };
+        let format = match stage {
+            Stage::Output => self.output_format,
+            _ => wgpu::TextureFormat::Rgba32Float,
+        };
+        Pipelines::create_pipeline(
+            device,
+            shader,
+            &Pipelines::create_pipeline_layout(device, layouts),
+            format,
+            stage.label(),
+        )
+    }
+}
+
+/// Pipelines for finding the min and max of the kernelled image
+pub enum MinMaxPipelines {
+    /// Parallel reduction using compute shaders
+    Compute {
+        texture_layout: wgpu::BindGroupLayout,
+        buffer_layout: wgpu::BindGroupLayout,
+        reduce_texture: wgpu::ComputePipeline,
+        reduce_buffer: wgpu::ComputePipeline,
+    },
+    /// Reduction using fragment shaders, for adapters without compute shaders
+    Fragment(wgpu::RenderPipeline),
 }
 
 /// Bind group layouts for textures
@@ -76,112 +209,264 @@ impl TextureBindGroupLayouts {
 pub struct Binding<'a>(pub u32, pub &'a wgpu::BindGroup);
 
 impl Pipelines {
-    /// Create a new Pipelines struct and load all shaders
+    /// Create a new Pipelines struct and load all shaders, failing if an
+    /// overridden shader does not parse or does not match its pipeline
     pub async fn new(
         device: &wgpu::Device,
         output_format: wgpu::TextureFormat,
         image_display_layout: &wgpu::BindGroupLayout,
-    ) -> Self {
+        compute_shaders: bool,
+    ) -> Result<Self> {
         // Create Texture Bind Group Layouts
         let layouts = TextureBindGroupLayouts::create(device);
 
         // Load Shaders
-        let s_interp = Pipelines::load_shader(device, "./src/shader/interpolation.wgsl").await;
-        let s_kernel = Pipelines::load_shader(device, "./src/shader/kernel.wgsl").await;
-        let s_for_loop = Pipelines::load_shader(device, "./src/shader/min_max.wgsl").await;
-        let s_normalize = Pipelines::load_shader(device, "./src/shader/normalize.wgsl").await;
-        let s_gamma_lut = Pipelines::load_shader(device, "./src/shader/gamma_lookup.wgsl").await;
-        let s_gamma = Pipelines::load_shader(device, "./src/shader/gamma_correction.wgsl").await;
-        let s_output = Pipelines::load_shader(device, "./src/shader/output.wgsl").await;
-
-        // Create Pipeline Layouts
-        let normal_layout = Pipelines::create_pipeline_layout(
-            device,
-            &[
-                &layouts.rgba32float,
-                image_display_layout,
-                &layouts.rgba32float,
+        let load = |stage: Stage| shaders::load(device, stage.shader(compute_shaders));
+        let s_interp = load(Stage::Interpolation).await?;
+        let s_mipmap = load(Stage::Mipmap).await?;
+        let s_kernel = load(Stage::Kernel).await?;
+        let s_min_max = load(Stage::MinMax).await?;
+        let s_normalize = load(Stage::Normalize).await?;
+        let s_gamma_lut = load(Stage::GammaLut).await?;
+        let s_gamma = load(Stage::Gamma).await?;
+        let s_convert = load(Stage::Convert).await?;
+        let s_output = load(Stage::Output).await?;
+
+        // Catch pipelines which do not match their shaders
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        // Placement of the textures of a pass within their full images
+        let region_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("region_bind_group_layout"),
+        });
+
+        // Tiles of the source image and their mip chains, with the layout of the tiles
+        let tiles = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2Array,
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            },
+            count: None,
+        };
+        let source_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                tiles(0),
+                tiles(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
-        );
+            label: Some("source_bind_group_layout"),
+        });
+
+        let min_max_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("min_max_bind_group_layout"),
+        });
+
+        let params_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("params_bind_group_layout"),
+        });
 
         // Create Pipelines
-        let interpolation = Pipelines::create_pipeline(
-            device,
-            s_interp,
-            &Pipelines::create_pipeline_layout(
-                device,
-                &[&layouts.bgra8unormsrgb, image_display_layout],
-            ),
-            wgpu::TextureFormat::Rgba32Float,
-            "interpolation",
-        );
-        let kernel = Pipelines::create_pipeline(
-            device,
-            s_kernel,
-            &normal_layout,
-            wgpu::TextureFormat::Rgba32Float,
-            "kernel",
-        );
-        let min_max = Pipelines::create_pipeline(
-            device,
-            s_for_loop,
-            &normal_layout,
-            wgpu::TextureFormat::Rgba32Float,
-            "min_max",
-        );
-        let normalize = Pipelines::create_pipeline(
-            device,
-            s_normalize,
-            &normal_layout,
-            wgpu::TextureFormat::Rgba32Float,
-            "normalize",
-        );
-        let gamma_lut = Pipelines::create_pipeline(
-            device,
-            s_gamma_lut,
-            &Pipelines::create_pipeline_layout(device, &[&image_display_layout]),
-            wgpu::TextureFormat::Rgba32Float,
-            "gamma_lut",
-        );
-        let gamma = Pipelines::create_pipeline(
-            device,
-            s_gamma,
-            &normal_layout,
-            wgpu::TextureFormat::Rgba32Float,
-            "gamma",
-        );
-        let output = Pipelines::create_pipeline(
-            device,
-            s_output,
-            &Pipelines::create_pipeline_layout(
-                device,
-                &[&layouts.rgba32float, &image_display_layout],
-            ),
+        let stages = StageLayouts {
+            textures: &layouts,
+            image_display: image_display_layout,
+            region: &region_layout,
+            source: &source_layout,
+            min_max: &min_max_layout,
             output_format,
-            "output",
-        );
+        };
+        let interpolation = stages.create(device, Stage::Interpolation, s_interp);
+        let mipmap = stages.create(device, Stage::Mipmap, s_mipmap);
+        let kernel = stages.create(device, Stage::Kernel, s_kernel);
+        let min_max = match compute_shaders {
+            true => MinMaxPipelines::create_compute(device, s_min_max),
+            false => MinMaxPipelines::Fragment(stages.create(device, Stage::MinMax, s_min_max)),
+        };
+        let normalize = stages.create(device, Stage::Normalize, s_normalize);
+        let gamma_lut = stages.create(device, Stage::GammaLut, s_gamma_lut);
+        let gamma = stages.create(device, Stage::Gamma, s_gamma);
+        let convert = stages.create(device, Stage::Convert, s_convert);
+        let output = stages.create(device, Stage::Output, s_output);
+
+        if let Some(error) = device.pop_error_scope().await {
+            return Err(anyhow!("Failed to create pipelines: {error}"));
+        }
 
         // Return pipelines struct
-        Pipelines {
+        Ok(Pipelines {
             bind_group_layouts: layouts,
             interpolation,
+            mipmap,
             kernel,
             min_max,
+            min_max_layout,
+            region_layout,
+            source_layout,
+            params_layout,
             normalize,
             gamma_lut,
             gamma,
+            convert,
             output,
-        }
+            output_format,
+            compute_shaders,
+        })
     }
 
-    /// Load shader bytes and create a shader module
-    async fn load_shader(device: &wgpu::Device, path: &str) -> wgpu::ShaderModule {
-        device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some(path),
-            source: wgpu::ShaderSource::Wgsl(String::from_utf8_lossy(
-                &load_bytes(path).await.unwrap(),
+    /// Rebuild the pipeline of the stage using a shader, keeping the previous
+    /// pipeline if the shader fails to parse or does not match the pipeline.
+    /// The mip chain of the loaded image is not regenerated, so a new mipmap
+    /// shader is used from the next image loaded
+    pub async fn reload(
+        &mut self,
+        device: &wgpu::Device,
+        image_display_layout: &wgpu::BindGroupLayout,
+        stage: Stage,
+    ) -> Result<(), Diagnostic> {
+        let name = stage.shader(self.compute_shaders);
+        let source = shaders::preprocess(name, shaders::source)?;
+        let shader = shaders::create_module(device, &source).await?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let stages = StageLayouts {
+            textures: &self.bind_group_layouts,
+            image_display: image_display_layout,
+            region: &self.region_layout,
+            source: &self.source_layout,
+            min_max: &self.min_max_layout,
+            output_format: self.output_format,
+        };
+        let compute = match (stage, &self.min_max) {
+            (
+                Stage::MinMax,
+                MinMaxPipelines::Compute {
+                    texture_layout,
+                    buffer_layout,
+                    ..
+                },
+            ) => Some(MinMaxPipelines::compute_pipelines(
+                device,
+                &shader,
+                texture_layout,
+                buffer_layout,
             )),
-        })
+            _ => None,
+        };
+        let render = compute
+            .is_none()
+            .then(|| stages.create(device, stage, shader));
+        if let Some(error) = device.pop_error_scope().await {
+            return Err(Diagnostic::new(name, None, error.to_string()));
+        }
+
+        if let Some(pipeline) = render {
+            *self.stage_mut(stage) = pipeline;
+        }
+        if let (
+            Some(pipelines),
+            MinMaxPipelines::Compute {
+                reduce_texture,
+                reduce_buffer,
+                ..
+            },
+        ) = (compute, &mut self.min_max)
+        {
+            (*reduce_texture, *reduce_buffer) = pipelines;
+        }
+        Ok(())
+    }
+
+    /// Create the pipeline of a filter plugin, which reads the image at group 0
+    /// with the display at group 1 and its parameters at group 2
+    pub async fn create_plugin(
+        &self,
+        device: &wgpu::Device,
+        image_display_layout: &wgpu::BindGroupLayout,
+        shader: &Preprocessed,
+    ) -> Result<wgpu::RenderPipeline, Diagnostic> {
+        let module = shaders::create_module(device, shader).await?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let layout = Pipelines::create_pipeline_layout(
+            device,
+            &[
+                &self.bind_group_layouts.rgba32float,
+                image_display_layout,
+                &self.params_layout,
+                &self.region_layout,
+            ],
+        );
+        let pipeline = Pipelines::create_pipeline(
+            device,
+            module,
+            &layout,
+            wgpu::TextureFormat::Rgba32Float,
+            &shader.name,
+        );
+        match device.pop_error_scope().await {
+            Some(error) => Err(Diagnostic::new(&shader.name, None, error.to_string())),
+            None => Ok(pipeline),
+        }
+    }
+
+    /// Render pipeline of a stage, the min max reduction is only a render
+    /// pipeline without compute shaders
+    fn stage_mut(&mut self, stage: Stage) -> &mut wgpu::RenderPipeline {
+        match stage {
+            Stage::Interpolation => &mut self.interpolation,
+            Stage::Mipmap => &mut self.mipmap,
+            Stage::Kernel => &mut self.kernel,
+            Stage::MinMax => match &mut self.min_max {
+                MinMaxPipelines::Fragment(pipeline) => pipeline,
+                MinMaxPipelines::Compute { .. } => unreachable!(),
+            },
+            Stage::Normalize => &mut self.normalize,
+            Stage::GammaLut => &mut self.gamma_lut,
+            Stage::Gamma => &mut self.gamma,
+            Stage::Convert => &mut self.convert,
+            Stage::Output => &mut self.output,
+        }
     }
 
     /// Create a layout from a list of bind groups
@@ -246,3 +531,74 @@ impl Pipelines {
         })
     }
 }
+
+impl MinMaxPipelines {
+    /// Create the compute pipelines for the parallel reduction
+    fn create_compute(device: &wgpu::Device, shader: wgpu::ShaderModule) -> Self {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        // Reads from the kernelled texture into the output buffer
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                storage(1, false),
+            ],
+            label: Some("min_max_texture_bind_group_layout"),
+        });
+
+        // Reads from the input buffer into the output buffer
+        let buffer_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[storage(1, false), storage(2, true)],
+            label: Some("min_max_buffer_bind_group_layout"),
+        });
+
+        let (reduce_texture, reduce_buffer) =
+            MinMaxPipelines::compute_pipelines(device, &shader, &texture_layout, &buffer_layout);
+
+        MinMaxPipelines::Compute {
+            texture_layout,
+            buffer_layout,
+            reduce_texture,
+            reduce_buffer,
+        }
+    }
+
+    /// Create the pipelines reducing the texture and the buffer from the shader
+    fn compute_pipelines(
+        device: &wgpu::Device,
+        shader: &wgpu::ShaderModule,
+        texture_layout: &wgpu::BindGroupLayout,
+        buffer_layout: &wgpu::BindGroupLayout,
+    ) -> (wgpu::ComputePipeline, wgpu::ComputePipeline) {
+        let create = |layout, entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&Pipelines::create_pipeline_layout(device, &[layout])),
+                module: shader,
+                entry_point,
+            })
+        };
+        (
+            create(texture_layout, "reduce_texture"),
+            create(buffer_layout, "reduce_buffer"),
+        )
+    }
+}
@@ -0,0 +1,309 @@
+use std::path::PathBuf;
+
+use wgpu::util::DeviceExt;
+
+use crate::{
+    pipelines::Pipelines,
+    shaders::{Diagnostic, Preprocessed},
+};
+
+/// Environment variable naming the directory filter plugins are loaded from
+pub const DIR_VAR: &str = "CS256_PLUGIN_DIR";
+
+/// Directory next to the executable plugins are loaded from when the variable is not set
+pub const DEFAULT_DIR: &str = "plugins";
+
+/// Group the uniform parameters of a plugin are bound at
+pub const PARAMS_GROUP: u32 = 2;
+
+/// Directory filter plugins are loaded from, the variable or the default directory
+/// next to the executable so it does not depend on the working directory. Never
+/// set on the web
+pub fn dir() -> Option<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            None
+        } else {
+            match std::env::var_os(DIR_VAR) {
+                Some(dir) => Some(PathBuf::from(dir)),
+                None => Some(std::env::current_exe().ok()?.parent()?.join(DEFAULT_DIR)),
+            }
+        }
+    }
+}
+
+/// Type of a parameter with the range shown in the ui
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ParamKind {
+    Float {
+        min: f32,
+        max: f32,
+    },
+    Int {
+        min: i32,
+        max: i32,
+    },
+    Uint {
+        min: u32,
+        max: u32,
+    },
+    /// A u32 shown as a checkbox, 1 when checked
+    Bool,
+}
+
+/// Value of a parameter as written to the uniform buffer
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ParamValue {
+    Float(f32),
+    Int(i32),
+    Uint(u32),
+    Bool(bool),
+}
+
+impl ParamValue {
+    /// Bytes of the value as laid out in the uniform
+    fn to_bytes(self) -> [u8; 4] {
+        match self {
+            Self::Float(v) => v.to_ne_bytes(),
+            Self::Int(v) => v.to_ne_bytes(),
+            Self::Uint(v) => v.to_ne_bytes(),
+            Self::Bool(v) => (v as u32).to_ne_bytes(),
+        }
+    }
+}
+
+/// Member of the uniform parameters of a plugin
+#[derive(Debug, PartialEq, Clone)]
+pub struct Param {
+    pub name: String,
+    pub kind: ParamKind,
+    /// Offset of the member within the uniform
+    pub offset: u32,
+    pub default: ParamValue,
+    pub value: ParamValue,
+}
+
+impl ParamKind {
+    /// Default range of a scalar type, None if the type cannot be a parameter
+    fn of(scalar: &naga::TypeInner) -> Option<Self> {
+        match scalar {
+            naga::TypeInner::Scalar { kind, width: 4 } => match kind {
+                naga::ScalarKind::Float => Some(Self::Float { min: 0.0, max: 1.0 }),
+                naga::ScalarKind::Sint => Some(Self::Int { min: 0, max: 10 }),
+                naga::ScalarKind::Uint => Some(Self::Uint { min: 0, max: 10 }),
+                naga::ScalarKind::Bool => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Parse a value of the kind from an annotation
+    fn parse(&self, value: &str) -> Option<ParamValue> {
+        let value = value.trim();
+        match self {
+            Self::Float { .. } => value.parse().ok().map(ParamValue::Float),
+            Self::Int { .. } => value.parse().ok().map(ParamValue::Int),
+            Self::Uint { .. } => value.parse().ok().map(ParamValue::Uint),
+            Self::Bool => match value {
+                "true" | "1" => Some(ParamValue::Bool(true)),
+                "false" | "0" => Some(ParamValue::Bool(false)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Smallest value of the range, the default unless one is annotated
+    fn min(&self) -> ParamValue {
+        match *self {
+            Self::Float { min, .. } => ParamValue::Float(min),
+            Self::Int { min, .. } => ParamValue::Int(min),
+            Self::Uint { min, .. } => ParamValue::Uint(min),
+            Self::Bool => ParamValue::Bool(false),
+        }
+    }
+}
+
+/// Find the uniform parameters a plugin declares at `@group(PARAMS_GROUP) @binding(0)`,
+/// returning them with the size of the uniform. Every member must be an f32, i32
+/// or u32, annotated in a comment on its line with `@range(min, max)`,
+/// `@default(value)` or `@checkbox` to show a u32 as a checkbox
+pub fn reflect(shader: &Preprocessed) -> Result<(Vec<Param>, u32), Diagnostic> {
+    let source = &shader.source;
+    let location = |span: naga::Span| {
+        let l = span.location(source);
+        Some((l.line_number, l.line_position))
+    };
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| shader.diagnostic(None, e.message().to_string()))?;
+
+    let Some((_, params)) = module.global_variables.iter().find(|(_, var)| {
+        var.binding.as_ref().map(|b| (b.group, b.binding)) == Some((PARAMS_GROUP, 0))
+    }) else {
+        return Ok((Vec::new(), 0));
+    };
+    let span = module.types.get_span(params.ty);
+    let (members, size) = match &module.types[params.ty].inner {
+        naga::TypeInner::Struct { members, span }
+            if params.space == naga::AddressSpace::Uniform =>
+        {
+            (members, *span)
+        }
+        _ => {
+            let message = "Parameters must be a uniform struct".to_string();
+            return Err(shader.diagnostic(location(span), message));
+        }
+    };
+
+    // Annotations are read from the lines of the struct declaration, the span
+    // of which ends at the type of the last member
+    let declaration = span
+        .to_range()
+        .and_then(|range| source[range.start..].split_once('}'))
+        .map_or("", |(declaration, _)| declaration);
+    let first_line = location(span).map_or(1, |(line, _)| line);
+    members
+        .iter()
+        .map(|member| {
+            let name = member.name.clone().unwrap_or_default();
+            let (line, comment) = declaration
+                .lines()
+                .enumerate()
+                .find_map(|(i, line)| {
+                    let rest = line.trim_start().strip_prefix(name.as_str())?;
+                    rest.trim_start().starts_with(':').then(|| {
+                        let comment = line.split_once("//").map_or("", |(_, c)| c);
+                        (first_line + i as u32, comment)
+                    })
+                })
+                .unwrap_or((first_line, ""));
+            let error = |message: String| shader.diagnostic(Some((line, 1)), message);
+
+            let mut kind = ParamKind::of(&module.types[member.ty].inner).ok_or_else(|| {
+                error(format!(
+                    "Parameter {name} must be an f32, i32 or u32 to be shown in the ui"
+                ))
+            })?;
+            let mut default = None;
+            for annotation in comment.split('@').skip(1) {
+                let (annotation, args) = match annotation.split_once('(') {
+                    Some((annotation, args)) => (
+                        annotation.trim(),
+                        args.split_once(')').map(|(args, _)| args),
+                    ),
+                    None => (annotation.trim(), None),
+                };
+                let invalid = || error(format!("Invalid @{annotation} for parameter {name}"));
+                match (annotation, args) {
+                    ("range", Some(args)) => {
+                        let (min, max) = args.split_once(',').ok_or_else(invalid)?;
+                        kind = match (kind, kind.parse(min), kind.parse(max)) {
+                            (
+                                ParamKind::Float { .. },
+                                Some(ParamValue::Float(min)),
+                                Some(ParamValue::Float(max)),
+                            ) => ParamKind::Float { min, max },
+                            (
+                                ParamKind::Int { .. },
+                                Some(ParamValue::Int(min)),
+                                Some(ParamValue::Int(max)),
+                            ) => ParamKind::Int { min, max },
+                            (
+                                ParamKind::Uint { .. },
+                                Some(ParamValue::Uint(min)),
+                                Some(ParamValue::Uint(max)),
+                            ) => ParamKind::Uint { min, max },
+                            _ => return Err(invalid()),
+                        };
+                    }
+                    ("default", Some(value)) => default = Some(value),
+                    ("checkbox", None) if matches!(kind, ParamKind::Uint { .. }) => {
+                        kind = ParamKind::Bool
+                    }
+                    ("range" | "default" | "checkbox", _) => return Err(invalid()),
+                    _ => {
+                        return Err(error(format!(
+                            "Unknown annotation @{annotation} for parameter {name}"
+                        )))
+                    }
+                }
+            }
+
+            // The default is parsed last, as the kind may be changed by a later annotation
+            let default = match default {
+                Some(value) => kind
+                    .parse(value)
+                    .ok_or_else(|| error(format!("Invalid @default for parameter {name}")))?,
+                None => kind.min(),
+            };
+            Ok(Param {
+                name,
+                kind,
+                offset: member.offset,
+                default,
+                value: default,
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(|params| (params, size))
+}
+
+/// Filter shader loaded from the plugin directory, added to the filter graph
+/// with its uniform parameters
+pub struct Plugin {
+    /// File name of the shader
+    pub name: String,
+    /// Parameters at their defaults, the values are stored by each node of the plugin
+    pub params: Vec<Param>,
+    pub pipeline: wgpu::RenderPipeline,
+    /// Size of the uniform buffer of the parameters
+    pub size: u64,
+}
+
+impl Plugin {
+    pub fn new(
+        name: &str,
+        pipeline: wgpu::RenderPipeline,
+        (params, size): (Vec<Param>, u32),
+    ) -> Self {
+        // Uniforms are a multiple of 16 bytes, a plugin without parameters
+        // still binds a buffer
+        Self {
+            name: name.to_string(),
+            params,
+            pipeline,
+            size: size.max(1).next_multiple_of(16) as u64,
+        }
+    }
+
+    /// Create a uniform buffer for the parameters of a node of the plugin
+    pub fn create_buffer(
+        &self,
+        device: &wgpu::Device,
+        pipelines: &Pipelines,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("plugin_params_buf"),
+            contents: &vec![0; self.size as usize],
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.params_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("plugin_params_bind_group"),
+        });
+        (buffer, bind_group)
+    }
+}
+
+/// Write the values of parameters to a uniform buffer
+pub fn write(queue: &wgpu::Queue, buffer: &wgpu::Buffer, params: &[Param]) {
+    let mut bytes = vec![0; buffer.size() as usize];
+    for param in params {
+        let offset = param.offset as usize;
+        bytes[offset..offset + 4].copy_from_slice(&param.value.to_bytes());
+    }
+    queue.write_buffer(buffer, 0, &bytes);
+}
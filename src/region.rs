@@ -0,0 +1,273 @@
+use wgpu::util::DeviceExt;
+
+use crate::pipelines::Pipelines;
+
+/// Rectangle within a full image, in pixels. With the wrapped border mode a
+/// region can reach past the edges of the image, into the copies of it around it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Region {
+    pub origin: (i32, i32),
+    pub size: (u32, u32),
+}
+
+/// Crop of the border mode and apron of a kernel, in pixels
+pub type KernelExtent = ((u32, u32), (u32, u32));
+
+/// Regions of each image processed by the stages. Only the visible region of the
+/// result of the last kernel is produced, from an interpolated region grown by the
+/// radius of every kernel so pixels at its edges see their real neighbours. The
+/// visible region is the viewport, or a tile of the whole image
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Regions {
+    /// Size of the full scaled image
+    pub scaled: (u32, u32),
+    /// Size of the full output image, cropped by every kernel with the valid border mode
+    pub output: (u32, u32),
+    /// Region of the scaled image which is interpolated
+    pub interpolated: Region,
+    /// Region of the output image which is processed and displayed
+    pub visible: Region,
+    /// Regions read and written by each kernel, in the order they run
+    pub kernels: Vec<KernelRegions>,
+}
+
+/// Region of the full image a kernel reads, and the region of its full result
+/// which it writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KernelRegions {
+    pub source: Region,
+    /// Size of the full image the kernel reads
+    pub source_size: (u32, u32),
+    pub target: Region,
+    /// Size of the full result, cropped by the valid border mode
+    pub target_size: (u32, u32),
+}
+
+/// Placement of the source and target textures of a pass within their full
+/// images, matching the PassRegion struct of uniforms.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RawPassRegion {
+    pub source_origin: [i32; 2],
+    pub source_size: [i32; 2],
+    pub target_origin: [i32; 2],
+    pub target_size: [i32; 2],
+}
+
+/// Uniform holding the placement of a pass
+pub struct PassRegion {
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// Placements of the passes that read or write part of an image, besides the kernels
+pub struct PassRegions {
+    pub interpolation: PassRegion,
+    pub output: PassRegion,
+}
+
+/// Placements of the passes of a kernel
+pub struct KernelPasses {
+    pub kernel: PassRegion,
+    /// Horizontal pass of a separable kernel
+    pub row: PassRegion,
+    /// Vertical pass of a separable kernel
+    pub column: PassRegion,
+}
+
+impl Region {
+    /// Region covering a whole image
+    pub fn whole(size: (u32, u32)) -> Region {
+        Region {
+            origin: (0, 0),
+            size,
+        }
+    }
+
+    /// Create a region from its first and one past its last pixel
+    fn from_bounds(first: (i32, i32), last: (i32, i32)) -> Region {
+        Region {
+            origin: first,
+            size: ((last.0 - first.0) as u32, (last.1 - first.1) as u32),
+        }
+    }
+
+    /// One past the last pixel of the region
+    pub fn end(&self) -> (i32, i32) {
+        (
+            self.origin.0 + self.size.0 as i32,
+            self.origin.1 + self.size.1 as i32,
+        )
+    }
+}
+
+impl Regions {
+    /// Size of the kerneled image for a scaled image and the crop of the border mode
+    pub fn output_size(scaled: (u32, u32), crop: (u32, u32)) -> (u32, u32) {
+        (
+            scaled.0.saturating_sub(crop.0).max(1),
+            scaled.1.saturating_sub(crop.1).max(1),
+        )
+    }
+
+    /// Find the regions each kernel reads for the visible region of the output,
+    /// working back from the last kernel with the radius of each as its apron.
+    /// Kernels are given by the crop of the border mode and their apron
+    pub fn new(scaled: (u32, u32), kernels: &[KernelExtent], visible: Region, wrap: bool) -> Self {
+        // Size of the full image read by each kernel, followed by the output
+        let mut sizes = vec![scaled];
+        for (crop, _) in kernels {
+            sizes.push(Regions::output_size(*sizes.last().unwrap(), *crop));
+        }
+
+        let mut target = visible;
+        let mut regions: Vec<_> = kernels
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, &(crop, apron))| {
+                let source = Regions::source(sizes[i], crop, apron, target, wrap);
+                let kernel = KernelRegions {
+                    source,
+                    source_size: sizes[i],
+                    target,
+                    target_size: sizes[i + 1],
+                };
+                target = source;
+                kernel
+            })
+            .collect();
+        regions.reverse();
+
+        Regions {
+            scaled,
+            output: sizes[kernels.len()],
+            interpolated: target,
+            visible,
+            kernels: regions,
+        }
+    }
+
+    /// Find the region of a full image a kernel needs for a region of its result.
+    /// The valid border mode shifts the result by half of its crop. A wrapped border
+    /// reads past the edges, from the copies of the image around it, so its region
+    /// is not clamped to the image
+    fn source(
+        size: (u32, u32),
+        crop: (u32, u32),
+        apron: (u32, u32),
+        target: Region,
+        wrap: bool,
+    ) -> Region {
+        let axis = |size: u32, crop: u32, apron: u32, first: i32, last: i32| {
+            let (size, apron) = (size as i32, apron as i32);
+            let (first, last) = (first + crop as i32 / 2, last + crop as i32 / 2);
+            match wrap {
+                true => (first - apron, last + apron),
+                false => {
+                    let first = (first - apron).clamp(0, size - 1);
+                    (first, (last + apron).clamp(first + 1, size))
+                }
+            }
+        };
+        let end = target.end();
+        let x = axis(size.0, crop.0, apron.0, target.origin.0, end.0);
+        let y = axis(size.1, crop.1, apron.1, target.origin.1, end.1);
+        Region::from_bounds((x.0, y.0), (x.1, y.1))
+    }
+}
+
+impl KernelRegions {
+    /// Region of the horizontal pass of a separable kernel, which has the columns
+    /// of the result and the rows of the source, with the size of its full image
+    pub fn separable(&self) -> (Region, (u32, u32)) {
+        let region = Region {
+            origin: (self.target.origin.0, self.source.origin.1),
+            size: (self.target.size.0, self.source.size.1),
+        };
+        (region, (self.target_size.0, self.source_size.1))
+    }
+}
+
+impl RawPassRegion {
+    /// Place a source region of a full image and a target region of a full image
+    pub fn new(
+        (source, source_size): (Region, (u32, u32)),
+        (target, target_size): (Region, (u32, u32)),
+    ) -> Self {
+        let int = |(x, y): (u32, u32)| [x as i32, y as i32];
+        RawPassRegion {
+            source_origin: [source.origin.0, source.origin.1],
+            source_size: int(source_size),
+            target_origin: [target.origin.0, target.origin.1],
+            target_size: int(target_size),
+        }
+    }
+}
+
+impl PassRegion {
+    /// Create the uniform and its bind group
+    pub fn new(device: &wgpu::Device, pipelines: &Pipelines) -> Self {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("pass_region_buf"),
+            contents: bytemuck::bytes_of(&RawPassRegion::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.region_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("pass_region_bind_group"),
+        });
+        PassRegion { buffer, bind_group }
+    }
+
+    /// Write the placement of the pass
+    pub fn write(&self, queue: &wgpu::Queue, raw: RawPassRegion) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&raw));
+    }
+}
+
+impl PassRegions {
+    /// Create a uniform for the interpolation and output passes
+    pub fn new(device: &wgpu::Device, pipelines: &Pipelines) -> Self {
+        PassRegions {
+            interpolation: PassRegion::new(device, pipelines),
+            output: PassRegion::new(device, pipelines),
+        }
+    }
+
+    /// Write the placement of the interpolation and output for the regions
+    pub fn write(&self, queue: &wgpu::Queue, regions: &Regions) {
+        let interpolated = (regions.interpolated, regions.scaled);
+        let visible = (regions.visible, regions.output);
+        self.interpolation
+            .write(queue, RawPassRegion::new(interpolated, interpolated));
+        self.output
+            .write(queue, RawPassRegion::new(visible, visible));
+    }
+}
+
+impl KernelPasses {
+    /// Create a uniform for every pass of a kernel
+    pub fn new(device: &wgpu::Device, pipelines: &Pipelines) -> Self {
+        KernelPasses {
+            kernel: PassRegion::new(device, pipelines),
+            row: PassRegion::new(device, pipelines),
+            column: PassRegion::new(device, pipelines),
+        }
+    }
+
+    /// Write the placement of every pass of the kernel
+    pub fn write(&self, queue: &wgpu::Queue, regions: &KernelRegions) {
+        let source = (regions.source, regions.source_size);
+        let target = (regions.target, regions.target_size);
+        let separable = regions.separable();
+        self.kernel.write(queue, RawPassRegion::new(source, target));
+        self.row.write(queue, RawPassRegion::new(source, separable));
+        self.column
+            .write(queue, RawPassRegion::new(separable, target));
+    }
+}
@@ -0,0 +1,17 @@
+// Resolving coordinates outside of an image for the border modes
+
+// Reflect a coordinate into the texture, repeating the edge pixel
+// The remainder is only taken of positive values, as it is undefined
+// for negative values on some backends
+fn mirror(pos: vec2<i32>, size: vec2<i32>) -> vec2<i32> {
+    let negative = pos < vec2<i32>(0);
+    let m = select(pos, -pos - 1, negative) % (size * 2);
+    return select(m, size * 2 - 1 - m, m >= size);
+}
+
+// Tile a coordinate into the texture
+fn wrap(pos: vec2<i32>, size: vec2<i32>) -> vec2<i32> {
+    let negative = pos < vec2<i32>(0);
+    let m = select(pos, -pos - 1, negative) % size;
+    return select(m, size - 1 - m, negative);
+}
@@ -0,0 +1,50 @@
+#define IMAGE_DISPLAY_GROUP 1
+
+#include "texture.wgsl"
+#include "image_display.wgsl"
+#include "vertex.wgsl"
+
+// Fragment shader
+
+// Decode channels encoded with the sRGB transfer curve,
+// values outside of 0..1 keep their sign
+fn decode(c: vec3<f32>) -> vec3<f32> {
+    let x = abs(c);
+    let curve = pow((x + 0.055) / 1.055, vec3<f32>(2.4));
+    return sign(c) * select(curve, x / 12.92, x <= vec3<f32>(0.04045));
+}
+
+// Convert a colour in the working space back to linear sRGB for output
+fn to_linear_srgb(colour: vec4<f32>) -> vec4<f32> {
+    var rgb = colour.xyz;
+    switch image_display.working_space {
+        // sRGB
+        case 1u: {
+            rgb = decode(rgb);
+        }
+        // Display P3, with the sRGB transfer curve. The matrices match colour.rs
+        case 2u: {
+            rgb = transpose(mat3x3<f32>(
+                1.2249401, -0.2249404, 0.0,
+                -0.0420569, 1.0420571, 0.0,
+                -0.0196376, -0.0786361, 1.0982735,
+            )) * decode(rgb);
+        }
+        // ACEScg
+        case 3u: {
+            rgb = transpose(mat3x3<f32>(
+                1.705051, -0.6217921, -0.083259,
+                -0.1302564, 1.1408047, -0.0105483,
+                -0.0240034, -0.128969, 1.1529724,
+            )) * rgb;
+        }
+        // Linear sRGB
+        default: {}
+    }
+    return vec4<f32>(rgb, colour.w);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return to_linear_srgb(load_pixel(vec2<i32>(in.clip_position.xy)));
+}
@@ -1,67 +1,55 @@
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0) @binding(1)
-var s_diffuse: sampler;
+#define IMAGE_DISPLAY_GROUP 1
+#define SOURCE_GROUP 3
 
-struct ImageDisplay {
-    window_size: vec2<f32>,
-    pos: vec2<f32>,
-    scale: f32,
-    gamma: f32,
-    scaling_mode: u32,
-};
+#include "texture.wgsl"
+#include "image_display.wgsl"
+#include "vertex.wgsl"
 
-@group(1) @binding(0)
-var<uniform> image_display : ImageDisplay;
+// Layout of the tiles of the source image, only read for its precision
+#include "source_tiles.wgsl"
 
 @group(2) @binding(0)
 var gamma_lut_diffuse: texture_2d<f32>;
 @group(2) @binding(1)
 var gamma_lut_sampler: sampler;
 
-// Vertex shader
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-};
-
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-    return out;
-}
-
 // Fragment shader
 
 // Lookup the value in the lookup table
 fn sample_lookup(i : f32) -> f32 {
-    let transformed = round(i * 256.0);
+    let transformed = clamp(round(i * 256.0), 0.0, 255.0);
     return textureSample(gamma_lut_diffuse, gamma_lut_sampler, vec2<f32>((transformed + 0.5) / 256.0, 0.5)).x;
 }
 
-// Apply gamma correction to a colour
+// Apply the gamma curve exactly, values outside of 0..1 keep their sign
+fn exact_gamma(i : f32) -> f32 {
+    return sign(i) * pow(abs(i), 1.0 / image_display.gamma);
+}
+
+// Gamma correct a channel, exactly for 16-bit and float sources as the
+// lookup table only has the precision of an 8-bit image
+fn correct(i : f32) -> f32 {
+    if tiles.exact_gamma != 0 {
+        return exact_gamma(i);
+    }
+    return sample_lookup(i);
+}
+
+// Apply gamma correction to a colour, alpha is only corrected when processed
 fn gamma_correction(colour: vec4<f32>) -> vec4<f32> {
+    var alpha = colour.w;
+    if image_display.process_alpha != 0u {
+        alpha = correct(alpha);
+    }
     return vec4<f32>(
-        sample_lookup(colour.x),
-        sample_lookup(colour.y),
-        sample_lookup(colour.z),
-        1.0
+        correct(colour.x),
+        correct(colour.y),
+        correct(colour.z),
+        alpha
     );
 }
 
-// Get the sample of the texture in at a pixel
-fn sample(pos : vec2<f32>) -> vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, (pos + vec2<f32>(0.5)) / vec2<f32>(textureDimensions(t_diffuse)));
-}
-
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return gamma_correction(sample(in.clip_position.xy));
+    return gamma_correction(load_pixel(vec2<i32>(in.clip_position.xy)));
 }
\ No newline at end of file
@@ -1,32 +1,7 @@
-struct ImageDisplay {
-    window_size: vec2<f32>,
-    pos: vec2<f32>,
-    scale: f32,
-    gamma: f32,
-    scaling_mode: u32,
-};
+#define IMAGE_DISPLAY_GROUP 0
 
-@group(0) @binding(0)
-var<uniform> image_display : ImageDisplay;
-
-// Vertex shader
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-};
-
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-    return out;
-}
+#include "image_display.wgsl"
+#include "vertex.wgsl"
 
 // Fragment shader
 
@@ -0,0 +1,6 @@
+// Settings of the display, bound at IMAGE_DISPLAY_GROUP
+
+#include "uniforms.wgsl"
+
+@group(IMAGE_DISPLAY_GROUP) @binding(0)
+var<uniform> image_display : ImageDisplay;
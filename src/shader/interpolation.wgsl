@@ -1,63 +1,163 @@
+#define SOURCE_GROUP 0
+#define IMAGE_DISPLAY_GROUP 1
+#define REGION_GROUP 2
+
+#include "source_tiles.wgsl"
+#include "image_display.wgsl"
+#include "region.wgsl"
+#include "vertex.wgsl"
+#include "border.wgsl"
+
+// Tiles of the source image and their mip chains
 @group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
+var t_diffuse: texture_2d_array<f32>;
 @group(0) @binding(1)
-var s_diffuse: sampler;
+var t_mip: texture_2d_array<f32>;
+
+// Get the size of the full source image into the shader
+fn tex_size() -> vec2<f32> {
+    return vec2<f32>(tiles.size);
+}
 
-struct ImageDisplay {
-    window_size: vec2<f32>,
-    pos: vec2<f32>,
-    scale: f32,
-    gamma: f32,
-    scaling_mode: u32,
-};
+// Size of a level of the full image, where level 0 is the image itself
+fn level_size(level: i32) -> vec2<i32> {
+    return max(tiles.size >> vec2<u32>(u32(level)), vec2<i32>(1));
+}
 
-@group(1) @binding(0)
-var<uniform> image_display : ImageDisplay;
+// Number of levels including the image itself, halving until a single pixel
+// is left or a tile is a single pixel
+fn level_count() -> i32 {
+    return tiles.levels;
+}
 
-// Vertex shader
+// Load a pixel of a level from the tile containing it
+fn load_level(coord: vec2<i32>, level: i32) -> vec4<f32> {
+    let tile_size = max(tiles.tile_size >> vec2<u32>(u32(level)), vec2<i32>(1));
+    let tile = coord / tile_size;
+    let layer = tile.y * tiles.columns + tile.x;
+    let local = coord - tile * tile_size;
+    if level == 0 {
+        return textureLoad(t_diffuse, local, layer, 0);
+    }
+    return textureLoad(t_mip, local, layer, level - 1);
+}
 
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-};
+// Level of detail for the scale, 0 when not using mipmaps or upscaling
+fn level_of_detail() -> f32 {
+    if image_display.mipmaps == 0u {
+        return 0.0;
+    }
+    return clamp(-log2(image_display.size), 0.0, f32(level_count() - 1));
+}
 
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-};
+// Encode linear channels with the sRGB transfer curve,
+// values outside of 0..1 keep their sign
+fn encode(c: vec3<f32>) -> vec3<f32> {
+    let x = abs(c);
+    let curve = 1.055 * pow(x, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return sign(c) * select(curve, x * 12.92, x <= vec3<f32>(0.0031308));
+}
 
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-    return out;
+// Convert a linear sRGB colour loaded from the source into the working space
+fn to_working_space(colour: vec4<f32>) -> vec4<f32> {
+    var rgb = colour.xyz;
+    switch image_display.working_space {
+        // sRGB
+        case 1u: {
+            rgb = encode(rgb);
+        }
+        // Display P3, with the sRGB transfer curve. The matrices match colour.rs
+        case 2u: {
+            rgb = encode(transpose(mat3x3<f32>(
+                0.8224621, 0.177538, 0.0,
+                0.0331941, 0.9668058, 0.0,
+                0.0170827, 0.0723974, 0.9105199,
+            )) * rgb);
+        }
+        // ACEScg
+        case 3u: {
+            rgb = transpose(mat3x3<f32>(
+                0.6130974, 0.3395231, 0.0473794,
+                0.0701937, 0.9163539, 0.0134524,
+                0.0206156, 0.1095698, 0.8698151,
+            )) * rgb;
+        }
+        // Linear sRGB
+        default: {}
+    }
+    return vec4<f32>(rgb, colour.w);
 }
 
-// Get the size of the texture into the shader
-fn tex_size() -> vec2<f32> {
-    return vec2<f32>(textureDimensions(t_diffuse));
+// Multiply the colour by its alpha, filtering premultiplied colours
+// so transparent pixels do not bleed their colour into their neighbours
+fn premultiply(colour: vec4<f32>) -> vec4<f32> {
+    return vec4<f32>(colour.xyz * colour.w, colour.w);
+}
+
+// Divide the filtered colour by its alpha, clamping alpha as the filters
+// can ring outside of 0..1. A transparent pixel has no colour
+fn unpremultiply(colour: vec4<f32>) -> vec4<f32> {
+    let alpha = clamp(colour.w, 0.0, 1.0);
+    if alpha <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+    return vec4<f32>(colour.xyz / alpha, alpha);
+}
+
+// Sample a level of the mip chain at the pixel coordinate, resolving
+// coordinates outside of the level with the border mode. The sample is
+// converted into the working space, which the border colour is already in,
+// and premultiplied by its alpha
+fn sample_level(pos: vec2<i32>, level: i32) -> vec4<f32> {
+    let size = level_size(level);
+    var coord: vec2<i32>;
+    switch image_display.border_mode {
+        // Mirror
+        case 1u: {
+            coord = mirror(pos, size);
+        }
+        // Wrap
+        case 2u: {
+            coord = wrap(pos, size);
+        }
+        // Constant
+        case 3u: {
+            if any(pos < vec2<i32>(0)) || any(pos >= size) {
+                return premultiply(image_display.border_colour);
+            }
+            coord = pos;
+        }
+        // Clamp and valid
+        default: {
+            coord = clamp(pos, vec2<i32>(0), size - 1);
+        }
+    }
+    return premultiply(to_working_space(load_level(coord, level)));
 }
 
 // Sample the texture at the pixel coordinate
-fn sample_pixel(pos : vec2<i32>) -> vec4<f32> {
-    let clamped = min(max(pos, vec2<i32>(0)), vec2<i32>(tex_size()));
-    let transformed = (vec2<f32>(clamped) + vec2<f32>(0.5)) / tex_size();
-    return textureSample(t_diffuse, s_diffuse, transformed);
+fn sample_pixel(pos: vec2<i32>) -> vec4<f32> {
+    return sample_level(pos, 0);
 }
 
-// Perform nearest neighbour interpolation for the coordinate
+// Transform the coordinate of an output pixel into a level of the mip chain
+fn level_coord(pos: vec2<f32>, level: i32) -> vec2<f32> {
+    return pos / image_display.size * vec2<f32>(level_size(level)) / tex_size();
+}
+
+// Perform nearest neighbour interpolation for the coordinate,
+// from the nearest level of the mip chain
 fn nearest_neighbour(pos: vec2<f32>) -> vec4<f32> {
-    let new_size = tex_size() * image_display.scale;
-    let transformed = tex_size() * pos / new_size;
+    let level = i32(round(level_of_detail()));
+    let transformed = level_coord(pos, level);
     let rounded = vec2<i32>(transformed);
 
-    return sample_pixel(rounded);
+    return sample_level(rounded, level);
 }
 
-// Perform billinear interpolation for the coordinate
-fn billinear(pos: vec2<f32>) -> vec4<f32> {
-    let new_size = tex_size() * image_display.scale;
-    let transformed = (tex_size() * pos / new_size) - 0.5;
+// Perform billinear interpolation for the coordinate within a level
+fn billinear_level(pos: vec2<f32>, level: i32) -> vec4<f32> {
+    let transformed = level_coord(pos, level) - 0.5;
 
     // Get all texture coordinates
     let top_left = floor(transformed);
@@ -66,10 +166,10 @@ fn billinear(pos: vec2<f32>) -> vec4<f32> {
     let bottom_right = top_left + vec2<f32>(1.0);
 
     // Sample all coordinates
-    let top_left_sample = sample_pixel(vec2<i32>(top_left));
-    let top_right_sample = sample_pixel(vec2<i32>(top_right));
-    let bottom_left_sample = sample_pixel(vec2<i32>(bottom_left));
-    let bottom_right_sample = sample_pixel(vec2<i32>(bottom_right));
+    let top_left_sample = sample_level(vec2<i32>(top_left), level);
+    let top_right_sample = sample_level(vec2<i32>(top_right), level);
+    let bottom_left_sample = sample_level(vec2<i32>(bottom_left), level);
+    let bottom_right_sample = sample_level(vec2<i32>(bottom_right), level);
 
     // Intepolate between Top Left and Top Right
     let top_middle = ((top_right.x - transformed.x) / (top_right.x - top_left.x)) * top_left_sample +
@@ -86,19 +186,158 @@ fn billinear(pos: vec2<f32>) -> vec4<f32> {
     return middle_middle;
 }
 
+// Perform billinear interpolation for the coordinate, blending
+// between the two closest levels of the mip chain
+fn billinear(pos: vec2<f32>) -> vec4<f32> {
+    let lod = level_of_detail();
+    let level = i32(floor(lod));
+    let blend = lod - f32(level);
+    let upper = billinear_level(pos, level);
+    if blend == 0.0 {
+        return upper;
+    }
+    return mix(upper, billinear_level(pos, level + 1), blend);
+}
+
+const PI: f32 = 3.14159265358979;
+
+// Normalized sinc function
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        return 1.0;
+    }
+    return sin(PI * x) / (PI * x);
+}
+
+// Cubic filter with the parameters B and C from Mitchell and Netravali
+fn cubic(distance: f32, b: f32, c: f32) -> f32 {
+    let x = abs(distance);
+    if x < 1.0 {
+        return ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b)) / 6.0;
+    } else if x < 2.0 {
+        return ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)) / 6.0;
+    }
+    return 0.0;
+}
+
+// Lanczos filter with a lobes
+fn lanczos(x: f32, a: f32) -> f32 {
+    if abs(x) >= a {
+        return 0.0;
+    }
+    return sinc(x) * sinc(x / a);
+}
+
+// Distance from the centre to the edge of the unstretched filter
+fn filter_support() -> f32 {
+    switch image_display.scaling_mode {
+        case 5u: {
+            return 3.0;
+        }
+        default: {
+            return 2.0;
+        }
+    }
+}
+
+// Evaluate the filter of the scaling mode at a distance from the centre
+fn filter_weight(x: f32) -> f32 {
+    switch image_display.scaling_mode {
+        // Catmull-Rom
+        case 2u: {
+            return cubic(x, 0.0, 0.5);
+        }
+        // Mitchell-Netravali
+        case 3u: {
+            return cubic(x, 1.0 / 3.0, 1.0 / 3.0);
+        }
+        // Lanczos-2
+        case 4u: {
+            return lanczos(x, 2.0);
+        }
+        // Lanczos-3
+        default: {
+            return lanczos(x, 3.0);
+        }
+    }
+}
+
+// Weight of a row or column of pixels for a centre in texture coordinates. The box
+// filter takes the overlap of the pixel with the area covered by the output pixel,
+// other filters are stretched when downscaling to filter out high frequencies
+fn axis_weight(pixel: i32, centre: f32) -> f32 {
+    let scale = image_display.size;
+    if image_display.scaling_mode == 6u {
+        let half = 0.5 / scale;
+        return max(0.0, min(f32(pixel) + 1.0, centre + half) - max(f32(pixel), centre - half));
+    }
+    let stretch = max(1.0 / scale, 1.0);
+    return filter_weight((f32(pixel) + 0.5 - centre) / stretch);
+}
+
+// Distance from the centre to the edge of the filter in texture pixels
+fn support_radius() -> f32 {
+    let scale = image_display.size;
+    if image_display.scaling_mode == 6u {
+        return 0.5 / scale;
+    }
+    return filter_support() * max(1.0 / scale, 1.0);
+}
+
+// Resample the texture with the filter of the scaling mode, normalizing
+// by the total weight as the filters do not always sum to one
+fn resample(pos: vec2<f32>) -> vec4<f32> {
+    let centre = pos / image_display.size;
+    let radius = support_radius();
+    let first = vec2<i32>(floor(centre - radius));
+    let last = vec2<i32>(ceil(centre + radius));
+
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var y = first.y; y <= last.y; y += 1) {
+        let weight_y = axis_weight(y, centre.y);
+        if weight_y == 0.0 {
+            continue;
+        }
+        for (var x = first.x; x <= last.x; x += 1) {
+            let weight = axis_weight(x, centre.x) * weight_y;
+            sum += sample_pixel(vec2<i32>(x, y)) * weight;
+            total += weight;
+        }
+    }
+    return sum / total;
+}
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let point = in.clip_position.xy;
+    // Only the region of the scaled image in the target is interpolated
+    var point = in.clip_position.xy + vec2<f32>(region.target_origin);
+
+    // A wrapped border reaches past the edges of the scaled image, into the
+    // copies of it around it
+    if image_display.border_mode == 2u {
+        let pixel = vec2<i32>(floor(point));
+        point += vec2<f32>(wrap(pixel, region.target_size) - pixel);
+    }
+    var colour: vec4<f32>;
     switch image_display.scaling_mode {
         case 0u: {
-            return nearest_neighbour(point);
+            colour = nearest_neighbour(point);
         }
         case 1u: {
-            return billinear(point);
+            colour = billinear(point);
+        }
+        case 2u, 3u, 4u, 5u, 6u: {
+            colour = resample(point);
         }
         default: {
             discard;
         }
     }
+    return unpremultiply(colour);
 }
\ No newline at end of file
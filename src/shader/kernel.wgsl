@@ -1,87 +1,79 @@
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0) @binding(1)
-var s_diffuse: sampler;
+#define IMAGE_DISPLAY_GROUP 1
+#define REGION_GROUP 3
 
-struct ImageDisplay {
-    window_size: vec2<f32>,
-    pos: vec2<f32>,
-    scale: f32,
-    gamma: f32,
-    scaling_mode: u32,
-};
-
-@group(1) @binding(0)
-var<uniform> image_display : ImageDisplay;
+#include "texture.wgsl"
+#include "image_display.wgsl"
+#include "region.wgsl"
+#include "vertex.wgsl"
+#include "border.wgsl"
 
 @group(2) @binding(0)
 var kernel_diffuse: texture_2d<f32>;
 @group(2) @binding(1)
 var kernel_sampler: sampler;
 
-// Vertex shader
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-};
-
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-    return out;
-}
-
 // Fragment shader
 
-// Sample the kernel at a position
-fn get_kernel_value(pos: vec2<f32>) -> f32 {
-    let kernel_dims = vec2<f32>(textureDimensions(kernel_diffuse));
-    let sample_pos = (pos + floor(kernel_dims / 2.0) + 0.5) / kernel_dims;
-    return unnorm(textureSample(kernel_diffuse, kernel_sampler, sample_pos).x);
+// Get the raw kernel weight at an offset from its centre
+fn get_kernel_value(offset: vec2<i32>, radius: vec2<i32>) -> f32 {
+    return textureLoad(kernel_diffuse, offset + radius, 0).x;
 }
 
-// Apply the kernel to a given coordinate
-// Returning values normalized from -128.0-128.0 to 0.0-1.0
-// otherwise the values are clipped to 0 and 1 by the rendering api
+// Apply the kernel to a given coordinate, the size of the kernel is
+// taken from the kernel texture and is always odd
+// Returning the signed sum, which the float target stores unclipped.
+// Unless alpha is processed, the alpha of the centre pixel is kept
 fn apply_kernel(pos: vec2<f32>) -> vec4<f32> {
-    var s = vec3<f32>(0.0);
-    for (var row = -2; row < 3; row += 1) {
-        for (var col = -2; col < 3; col += 1) {
-            let i = (row + 2) * 5 + (col + 2);
-            let sample_pos = vec2<i32>(pos + vec2<f32>(f32(row), f32(col)));
-            s += sample(sample_pos).xyz * get_kernel_value(vec2<f32>(f32(row), f32(col)));
-        }
-    }
-    return vec4<f32>(norm(s.x), norm(s.y), norm(s.z), 1.0);
-}
-
-// Normalize -128.0-128.0 to 0.0-1.0
-fn norm(in: f32) -> f32 {
-    return max(0.0, min(1.0, (in / 256.0) + 0.5));
-}
+    let radius = vec2<i32>(textureDimensions(kernel_diffuse)) / 2;
 
-// Un-Normalize 0.0-1.0 to -128.0-128.0
-fn unnorm(in: f32) -> f32 {
-    return (in - 0.5) * 256.0;
-}
+    // The valid border mode crops the output, so shift to the first pixel
+    // whose kernel lies inside the full image
+    var centre = vec2<i32>(floor(pos)) + region.target_origin;
+    if image_display.border_mode == 4u {
+        centre += radius;
+    }
 
-// Get the size of the texture into the shader
-fn tex_size() -> vec2<f32> {
-    return vec2<f32>(textureDimensions(t_diffuse));
+    var s = vec4<f32>(0.0);
+    for (var dy = -radius.y; dy <= radius.y; dy += 1) {
+        for (var dx = -radius.x; dx <= radius.x; dx += 1) {
+            let offset = vec2<i32>(dx, dy);
+            s += sample_pixel(centre + offset) * get_kernel_value(offset, radius);
+        }
+    }
+    if image_display.process_alpha == 0u {
+        s.w = sample_pixel(centre).w;
+    }
+    return s;
 }
 
-// Sample the texture at the pixel coordinate
-fn sample(pos : vec2<i32>) -> vec4<f32> {
-    let clamped = min(max(vec2<i32>(pos), vec2<i32>(0)), vec2<i32>(tex_size()));
-    let transformed = (vec2<f32>(clamped) + vec2<f32>(0.5)) / tex_size();
-    return textureSample(t_diffuse, s_diffuse, transformed);
+// Sample the full image at the pixel coordinate, resolving coordinates
+// outside of the full image with the border mode. The texture only holds
+// the region of the full image which the kernel reaches
+fn sample_pixel(pos: vec2<i32>) -> vec4<f32> {
+    let size = region.source_size;
+    var coord: vec2<i32>;
+    switch image_display.border_mode {
+        // Mirror
+        case 1u: {
+            coord = mirror(pos, size);
+        }
+        // Wrap, the region already holds the copies of the image past its edges
+        case 2u: {
+            coord = pos;
+        }
+        // Constant
+        case 3u: {
+            if any(pos < vec2<i32>(0)) || any(pos >= size) {
+                return image_display.border_colour;
+            }
+            coord = pos;
+        }
+        // Clamp and valid
+        default: {
+            coord = clamp(pos, vec2<i32>(0), size - 1);
+        }
+    }
+    return textureLoad(t_diffuse, coord - region.source_origin, 0);
 }
 
 @fragment
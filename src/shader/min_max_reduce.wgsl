@@ -0,0 +1,74 @@
+// Parallel reduction of the kernelled image to its min and max value
+// The first pass reduces 16x16 blocks of the texture into a buffer, then each
+// following pass reduces 256 values of the buffer until a single value is left
+
+#include "reduce.wgsl"
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> output: array<vec2<f32>>;
+
+@group(0) @binding(2)
+var<storage, read> input: array<vec2<f32>>;
+
+const WORKGROUP_SIZE: u32 = 256u;
+
+var<workgroup> shared_min: array<f32, WORKGROUP_SIZE>;
+var<workgroup> shared_max: array<f32, WORKGROUP_SIZE>;
+
+// Reduce the values in shared memory as a tree, halving the
+// active invocations each step until the result is in the first element
+fn reduce_shared(local_index: u32, value: vec2<f32>) -> vec2<f32> {
+    shared_min[local_index] = value.x;
+    shared_max[local_index] = value.y;
+    workgroupBarrier();
+
+    for (var stride = WORKGROUP_SIZE / 2u; stride > 0u; stride = stride / 2u) {
+        if local_index < stride {
+            shared_min[local_index] = min(shared_min[local_index], shared_min[local_index + stride]);
+            shared_max[local_index] = max(shared_max[local_index], shared_max[local_index + stride]);
+        }
+        workgroupBarrier();
+    }
+    return vec2<f32>(shared_min[0], shared_max[0]);
+}
+
+@compute @workgroup_size(16, 16)
+fn reduce_texture(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    // Invocations outside of the texture contribute the identity
+    var value = vec2<f32>(MAX_F32, -MAX_F32);
+    if all(global_id.xy < textureDimensions(t_diffuse)) {
+        let s = textureLoad(t_diffuse, vec2<i32>(global_id.xy), 0).xyz;
+        value = vec2<f32>(min_in_vec(s), max_in_vec(s));
+    }
+
+    let reduced = reduce_shared(local_index, value);
+    if local_index == 0u {
+        output[workgroup_id.y * num_workgroups.x + workgroup_id.x] = reduced;
+    }
+}
+
+@compute @workgroup_size(256)
+fn reduce_buffer(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+) {
+    // Invocations outside of the buffer contribute the identity
+    var value = vec2<f32>(MAX_F32, -MAX_F32);
+    if global_id.x < arrayLength(&input) {
+        value = input[global_id.x];
+    }
+
+    let reduced = reduce_shared(local_index, value);
+    if local_index == 0u {
+        output[workgroup_id.x] = reduced;
+    }
+}
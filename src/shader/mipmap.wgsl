@@ -0,0 +1,28 @@
+#include "texture.wgsl"
+#include "vertex.wgsl"
+
+// Fragment shader
+
+// Average the 2x2 block of the level above, weighting colours by their alpha so
+// transparent pixels do not contribute their colour. An odd row or column is
+// dropped and a level one pixel wide repeats its edge
+fn downsample(pos: vec2<i32>) -> vec4<f32> {
+    let last = vec2<i32>(textureDimensions(t_diffuse)) - 1;
+    let origin = pos * 2;
+    let top_left = textureLoad(t_diffuse, min(origin, last), 0);
+    let top_right = textureLoad(t_diffuse, min(origin + vec2<i32>(1, 0), last), 0);
+    let bottom_left = textureLoad(t_diffuse, min(origin + vec2<i32>(0, 1), last), 0);
+    let bottom_right = textureLoad(t_diffuse, min(origin + vec2<i32>(1, 1), last), 0);
+    let alpha = top_left.w + top_right.w + bottom_left.w + bottom_right.w;
+    if alpha <= 0.0 {
+        return vec4<f32>(0.0);
+    }
+    let colour = top_left.xyz * top_left.w + top_right.xyz * top_right.w
+        + bottom_left.xyz * bottom_left.w + bottom_right.xyz * bottom_right.w;
+    return vec4<f32>(colour / alpha, alpha * 0.25);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return downsample(vec2<i32>(in.clip_position.xy));
+}
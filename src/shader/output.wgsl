@@ -1,54 +1,37 @@
-@group(0) @binding(0)
-var t_diffuse: texture_2d<f32>;
-@group(0) @binding(1)
-var s_diffuse: sampler;
+#define IMAGE_DISPLAY_GROUP 1
+#define REGION_GROUP 2
 
-struct ImageDisplay {
-    window_size: vec2<f32>,
-    pos: vec2<f32>,
-    scale: f32,
-    gamma: f32,
-    scaling_mode: u32,
-};
-
-@group(1) @binding(0)
-var<uniform> image_display : ImageDisplay;
-
-// Vertex shader
-
-struct VertexInput {
-    @location(0) position: vec3<f32>,
-};
-
-struct VertexOutput {
-    @builtin(position) clip_position: vec4<f32>,
-};
-
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var out: VertexOutput;
-    out.clip_position = vec4<f32>(model.position, 1.0);
-    return out;
-}
+#include "texture.wgsl"
+#include "image_display.wgsl"
+#include "region.wgsl"
+#include "vertex.wgsl"
 
 // Fragment shader
 
-// Get the size of the texture into the shader
-fn tex_size() -> vec2<f32> {
-    return vec2<f32>(textureDimensions(t_diffuse));
-}
-
 // Sample the texture
 fn sample(pos : vec2<f32>) -> vec4<f32> {
     return textureSample(t_diffuse, s_diffuse, pos);
 }
 
-// Map the screen position to the texture position
-// accounting for scale and positioning
+// Map the screen position to the texture position accounting for scale and
+// positioning, the texture only holds a region of the full image
 fn screen_pos_to_tex_coord(pos: vec2<f32>) -> vec2<f32> {
-    return ((pos - image_display.pos - image_display.window_size / 2.0 + tex_size() / 2.0) / tex_size());
+    let full_size = vec2<f32>(region.source_size);
+    let image_pos = pos - image_display.pos - image_display.window_size / 2.0 + full_size / 2.0;
+    return (image_pos - vec2<f32>(region.source_origin)) / tex_size();
+}
+
+// Size of the squares of the checkerboard in screen pixels
+const CHECKER_SIZE: f32 = 8.0;
+
+// Light or dark grey for the square of the checkerboard at a position of the
+// image, so the checkerboard moves with the image
+fn checkerboard(pos: vec2<f32>) -> vec3<f32> {
+    let square = vec2<i32>(floor(pos / CHECKER_SIZE));
+    if ((square.x + square.y) & 1) == 0 {
+        return vec3<f32>(0.4);
+    }
+    return vec3<f32>(0.2);
 }
 
 @fragment
@@ -58,5 +41,9 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     if tex_pos.x < 0.0 || tex_pos.y < 0.0 || tex_pos.x > 1.0 || tex_pos.y > 1.0 {
         discard;
     }
-    return sample(tex_pos);
+    // Show transparent pixels over a checkerboard
+    let colour = sample(tex_pos);
+    let image_pos = in.clip_position.xy - image_display.pos - image_display.window_size / 2.0;
+    let alpha = clamp(colour.w, 0.0, 1.0);
+    return vec4<f32>(mix(checkerboard(image_pos), colour.xyz, alpha), 1.0);
 }
\ No newline at end of file
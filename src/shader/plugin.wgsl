@@ -0,0 +1,32 @@
+// Prelude included by filter plugins, which define the function applied to each
+// pixel of the image in the working space before gamma correction:
+//
+//     fn apply(colour: vec4<f32>, pixel: vec2<i32>) -> vec4<f32>
+//
+// The pixel is the coordinate within the full image, as only the part of the
+// image visible or in a tile may be processed. Only the colour given can be
+// relied on, the viewport and tiles hold no pixels around them for plugins.
+// Parameters are a uniform struct bound at @group(PARAMS_GROUP) @binding(0)
+
+#define IMAGE_DISPLAY_GROUP 1
+#define PARAMS_GROUP 2
+#define REGION_GROUP 3
+
+#include "texture.wgsl"
+#include "image_display.wgsl"
+#include "region.wgsl"
+#include "vertex.wgsl"
+#include "border.wgsl"
+
+// Fragment shader
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let local = vec2<i32>(in.clip_position.xy);
+    var pixel = local + region.target_origin;
+    // Wrap, the region holds copies of the image past its edges
+    if image_display.border_mode == 2u {
+        pixel = wrap(pixel, region.target_size);
+    }
+    return apply(load_pixel(local), pixel);
+}
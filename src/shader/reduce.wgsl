@@ -0,0 +1,14 @@
+// Helpers for reducing the kernelled image to its min and max
+
+// Largest finite f32, used as the identity of min and max
+const MAX_F32: f32 = 3.40282347e+38;
+
+// Get the min value stored in a vec3
+fn min_in_vec(colour : vec3<f32>) -> f32 {
+    return min(colour.x, min(colour.y, colour.z));
+}
+
+// Get the max value stored in a vec3
+fn max_in_vec(colour : vec3<f32>) -> f32 {
+    return max(colour.x, max(colour.y, colour.z));
+}
@@ -0,0 +1,6 @@
+// Placement of the pass, bound at REGION_GROUP
+
+#include "uniforms.wgsl"
+
+@group(REGION_GROUP) @binding(0)
+var<uniform> region: PassRegion;
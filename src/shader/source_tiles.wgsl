@@ -0,0 +1,6 @@
+// Layout of the tiles of the source image, bound beside the tiles at SOURCE_GROUP
+
+#include "uniforms.wgsl"
+
+@group(SOURCE_GROUP) @binding(2)
+var<uniform> tiles: SourceTiles;
@@ -0,0 +1,17 @@
+// Texture read by a pass, bound at group 0
+
+@group(0) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(0) @binding(1)
+var s_diffuse: sampler;
+
+// Get the size of the texture into the shader
+fn tex_size() -> vec2<f32> {
+    return vec2<f32>(textureDimensions(t_diffuse));
+}
+
+// Load the texture at the pixel coordinate, clamped to the texture
+fn load_pixel(pixel: vec2<i32>) -> vec4<f32> {
+    let last = vec2<i32>(textureDimensions(t_diffuse)) - 1;
+    return textureLoad(t_diffuse, clamp(pixel, vec2<i32>(0), last), 0);
+}
@@ -0,0 +1,37 @@
+// Uniform structs shared between the shaders, each mirrors the layout of the
+// raw struct written from Rust which the build checks member by member
+
+// Mirrors RawImageDisplay
+struct ImageDisplay {
+    window_size: vec2<f32>,
+    pos: vec2<f32>,
+    size: f32,
+    gamma: f32,
+    scaling_mode: u32,
+    border_mode: u32,
+    border_colour: vec4<f32>,
+    mipmaps: u32,
+    process_alpha: u32,
+    working_space: u32,
+    _pad: u32,
+};
+
+// Layout of the tiles the source image is split into, each a layer of the
+// texture and of its mip chain. Mirrors RawSourceTiles
+struct SourceTiles {
+    size: vec2<i32>,
+    tile_size: vec2<i32>,
+    columns: i32,
+    levels: i32,
+    exact_gamma: i32,
+    _pad: i32,
+};
+
+// Placement of the source and target textures within their full images.
+// Mirrors RawPassRegion
+struct PassRegion {
+    source_origin: vec2<i32>,
+    source_size: vec2<i32>,
+    target_origin: vec2<i32>,
+    target_size: vec2<i32>,
+};
@@ -0,0 +1,373 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Context, Result};
+use instant::Instant;
+
+/// Environment variable naming a directory of shaders used in place of the
+/// embedded shaders, so shaders can be edited without rebuilding
+pub const OVERRIDE_VAR: &str = "CS256_SHADER_DIR";
+
+// Checks generated by the build script that the uniform structs in uniforms.wgsl
+// match the layout of the raw structs written to them
+include!(concat!(env!("OUT_DIR"), "/uniforms.rs"));
+
+/// Shaders compiled into the binary by file name, followed by the files
+/// they include
+const EMBEDDED: [(&str, &str); 19] = [
+    (
+        "interpolation.wgsl",
+        include_str!("shader/interpolation.wgsl"),
+    ),
+    ("mipmap.wgsl", include_str!("shader/mipmap.wgsl")),
+    ("kernel.wgsl", include_str!("shader/kernel.wgsl")),
+    ("min_max.wgsl", include_str!("shader/min_max.wgsl")),
+    (
+        "min_max_reduce.wgsl",
+        include_str!("shader/min_max_reduce.wgsl"),
+    ),
+    ("normalize.wgsl", include_str!("shader/normalize.wgsl")),
+    (
+        "gamma_lookup.wgsl",
+        include_str!("shader/gamma_lookup.wgsl"),
+    ),
+    (
+        "gamma_correction.wgsl",
+        include_str!("shader/gamma_correction.wgsl"),
+    ),
+    ("convert.wgsl", include_str!("shader/convert.wgsl")),
+    ("output.wgsl", include_str!("shader/output.wgsl")),
+    ("uniforms.wgsl", include_str!("shader/uniforms.wgsl")),
+    (
+        "image_display.wgsl",
+        include_str!("shader/image_display.wgsl"),
+    ),
+    ("region.wgsl", include_str!("shader/region.wgsl")),
+    (
+        "source_tiles.wgsl",
+        include_str!("shader/source_tiles.wgsl"),
+    ),
+    ("vertex.wgsl", include_str!("shader/vertex.wgsl")),
+    ("texture.wgsl", include_str!("shader/texture.wgsl")),
+    ("border.wgsl", include_str!("shader/border.wgsl")),
+    ("reduce.wgsl", include_str!("shader/reduce.wgsl")),
+    ("plugin.wgsl", include_str!("shader/plugin.wgsl")),
+];
+
+/// Get the embedded source of a shader
+pub fn embedded(name: &str) -> Option<&'static str> {
+    EMBEDDED
+        .iter()
+        .find(|(embedded, _)| *embedded == name)
+        .map(|(_, source)| *source)
+}
+
+/// Directory set to override the embedded shaders, never set on the web
+pub fn override_dir() -> Option<PathBuf> {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            None
+        } else {
+            std::env::var_os(OVERRIDE_VAR).map(PathBuf::from)
+        }
+    }
+}
+
+/// Get the source of a shader, from the override directory if it holds the shader
+/// and otherwise embedded
+pub fn source(name: &str) -> Result<Cow<'static, str>> {
+    if let Some(path) = override_dir().map(|dir| dir.join(name)) {
+        if path.exists() {
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read shader {}", path.display()))?;
+            return Ok(Cow::Owned(source));
+        }
+    }
+    embedded(name)
+        .map(Cow::Borrowed)
+        .ok_or_else(|| anyhow!("No shader named {name}"))
+}
+
+/// Error in a shader, with the location it was found at when known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// File name of the shader
+    pub file: String,
+    /// Line and column, both starting from 1
+    pub location: Option<(u32, u32)>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: &str, location: Option<(u32, u32)>, message: String) -> Self {
+        Self {
+            file: file.to_string(),
+            location,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Some((line, column)) => write!(f, "{}:{line}:{column}: {}", self.file, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// Source of a shader with its includes expanded and defines substituted,
+/// remembering the file and line each line came from
+pub struct Preprocessed {
+    pub name: String,
+    pub source: String,
+    /// Files read, starting with the shader itself
+    files: Vec<String>,
+    /// Index of the file and line within it of each line of the source
+    lines: Vec<(usize, u32)>,
+}
+
+impl Preprocessed {
+    /// Files read, starting with the shader itself
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+
+    /// Create a diagnostic at a location in the expanded source, located
+    /// in the file the line came from
+    pub fn diagnostic(&self, location: Option<(u32, u32)>, message: String) -> Diagnostic {
+        let origin = location.and_then(|(line, column)| {
+            let (file, line) = self.lines.get(line as usize - 1)?;
+            Some((&self.files[*file], (*line, column)))
+        });
+        match origin {
+            Some((file, location)) => Diagnostic::new(file, Some(location), message),
+            None => Diagnostic::new(&self.name, location, message),
+        }
+    }
+
+    /// Expand the lines of a file into the source. An `#include "file.wgsl"` line
+    /// is replaced by that file, unless it has already been included, and a
+    /// `#define NAME value` line replaces NAME in every following line
+    fn expand(
+        &mut self,
+        file: usize,
+        source: &str,
+        load: &impl Fn(&str) -> Result<Cow<'static, str>>,
+        defines: &mut Vec<(String, String)>,
+    ) -> Result<(), Diagnostic> {
+        for (i, line) in source.lines().enumerate() {
+            let number = i as u32 + 1;
+            let error = |message: String| {
+                let column = (line.len() - line.trim_start().len()) as u32 + 1;
+                Diagnostic::new(&self.files[file], Some((number, column)), message)
+            };
+            let directive = line.trim();
+            if let Some(path) = directive.strip_prefix("#include") {
+                let path = path
+                    .trim()
+                    .strip_prefix('"')
+                    .and_then(|path| path.strip_suffix('"'))
+                    .ok_or_else(|| error("Expected #include \"file.wgsl\"".to_string()))?;
+                if !self.files.iter().any(|f| f == path) {
+                    let included =
+                        load(path).map_err(|e| error(format!("Failed to include {path}: {e}")))?;
+                    self.files.push(path.to_string());
+                    self.expand(self.files.len() - 1, &included, load, defines)?;
+                }
+            } else if let Some(define) = directive.strip_prefix("#define") {
+                let mut parts = define.trim().splitn(2, char::is_whitespace);
+                let name = parts
+                    .next()
+                    .filter(|name| is_identifier(name))
+                    .ok_or_else(|| error("Expected #define NAME value".to_string()))?;
+                let value = parts.next().unwrap_or_default().trim();
+                defines.retain(|(defined, _)| defined != name);
+                defines.push((name.to_string(), value.to_string()));
+            } else if directive.starts_with('#') {
+                return Err(error(format!("Unknown directive {directive}")));
+            } else {
+                self.source.push_str(&substitute(line, defines));
+                self.source.push('\n');
+                self.lines.push((file, number));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a name can be defined, starting with a letter or underscore
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Replace every identifier in the line which has been defined with its value.
+/// A trailing `//` comment is kept as it is, so annotations are not rewritten
+fn substitute(line: &str, defines: &[(String, String)]) -> String {
+    if defines.is_empty() {
+        return line.to_string();
+    }
+    let (code, comment) = line.split_at(line.find("//").unwrap_or(line.len()));
+    let mut out = String::with_capacity(line.len());
+    let mut rest = code;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric() || c == '_') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let word = &rest[..end];
+        match defines.iter().find(|(name, _)| name == word) {
+            Some((_, value)) => out.push_str(value),
+            None => out.push_str(word),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out.push_str(comment);
+    out
+}
+
+/// Preprocess a shader, reading it and the files it includes with the loader
+pub fn preprocess(
+    name: &str,
+    load: impl Fn(&str) -> Result<Cow<'static, str>>,
+) -> Result<Preprocessed, Diagnostic> {
+    let source = load(name).map_err(|e| Diagnostic::new(name, None, e.to_string()))?;
+    let mut shader = Preprocessed {
+        name: name.to_string(),
+        source: String::new(),
+        files: vec![name.to_string()],
+        lines: Vec::new(),
+    };
+    shader.expand(0, &source, &load, &mut Vec::new())?;
+    Ok(shader)
+}
+
+/// Parse and validate a shader with naga, as wgpu does when creating the module,
+/// keeping the location of the first error within the file it came from
+pub fn validate(shader: &Preprocessed) -> Result<(), Diagnostic> {
+    let source = &shader.source;
+    let location = |l: Option<naga::SourceLocation>| l.map(|l| (l.line_number, l.line_position));
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| shader.diagnostic(location(e.location(source)), e.message().to_string()))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| {
+        // The last span is the most specific, such as a statement within a function
+        let at = location(e.spans().last().map(|(span, _)| span.location(source)));
+        // The causes of a validation error hold the detail
+        let e = e.into_inner();
+        let mut message = e.to_string();
+        let mut cause = e.source();
+        while let Some(c) = cause {
+            message = format!("{message}: {c}");
+            cause = c.source();
+        }
+        shader.diagnostic(at, message)
+    })?;
+    Ok(())
+}
+
+/// Create a shader module, returning an error rather than panicking if it fails to parse
+pub async fn create_module(
+    device: &wgpu::Device,
+    shader: &Preprocessed,
+) -> Result<wgpu::ShaderModule, Diagnostic> {
+    validate(shader)?;
+
+    // Limits of the device are only checked by wgpu
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(&shader.name),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(&shader.source)),
+    });
+    match device.pop_error_scope().await {
+        Some(error) => Err(Diagnostic::new(&shader.name, None, error.to_string())),
+        None => Ok(module),
+    }
+}
+
+/// Load a shader by file name with its includes and create its module
+pub async fn load(device: &wgpu::Device, name: &str) -> Result<wgpu::ShaderModule> {
+    let module = match preprocess(name, source) {
+        Ok(shader) => create_module(device, &shader).await,
+        Err(e) => Err(e),
+    };
+    module.map_err(|e| anyhow!("Failed to parse shader {e}"))
+}
+
+/// Watches a directory of shaders for files being written, added or removed,
+/// so they can be reloaded while running
+pub struct Watcher {
+    dir: PathBuf,
+    modified: Vec<(String, SystemTime)>,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    /// How often the directory is checked
+    const INTERVAL: Duration = Duration::from_millis(250);
+
+    /// Watch a directory, which may not exist yet
+    pub fn new(dir: PathBuf) -> Self {
+        let modified = Watcher::modified(&dir);
+        Self {
+            dir,
+            modified,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// Modification time of every shader in the directory, by name
+    fn modified(dir: &std::path::Path) -> Vec<(String, SystemTime)> {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut modified: Vec<_> = entries
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().into_string().ok()?;
+                let time = entry.metadata().and_then(|m| m.modified()).ok()?;
+                name.ends_with(".wgsl").then_some((name, time))
+            })
+            .collect();
+        modified.sort();
+        modified
+    }
+
+    /// Names of the shaders written, added or removed since the last call
+    pub fn changed(&mut self) -> Vec<String> {
+        if self.last_poll.elapsed() < Watcher::INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let modified = Watcher::modified(&self.dir);
+        let mut changed: Vec<String> = modified
+            .iter()
+            .filter(|file| !self.modified.contains(file))
+            .chain(
+                self.modified
+                    .iter()
+                    .filter(|(name, _)| !modified.iter().any(|(new, _)| new == name)),
+            )
+            .map(|(name, _)| name.clone())
+            .collect();
+        changed.sort();
+        self.modified = modified;
+        changed
+    }
+}
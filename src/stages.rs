@@ -1,4 +1,18 @@
-use crate::{context::GraphicsContext, pipelines::Pipelines};
+use std::{future::Future, mem};
+
+use anyhow::{anyhow, Result};
+use futures::channel::oneshot;
+use image::{imageops, DynamicImage, Rgba32FImage};
+
+use crate::{
+    graph::Filter,
+    headless::HeadlessContext,
+    image_display::ImageDisplay,
+    kernel::Kernel,
+    pipelines::{Binding, MinMaxPipelines, Pipelines},
+    plugins,
+    region::{KernelPasses, KernelRegions, PassRegion, PassRegions, RawPassRegion, Regions},
+};
 
 /// Wrapper struct around a render target and source
 pub struct RenderGroup {
@@ -10,7 +24,7 @@ pub struct RenderGroup {
 impl RenderGroup {
     /// Create a new Render Group with texture, view and bindgroup
     pub fn new(
-        context: &GraphicsContext,
+        context: &HeadlessContext,
         dims: (u32, u32),
         format: wgpu::TextureFormat,
     ) -> RenderGroup {
@@ -23,7 +37,7 @@ impl RenderGroup {
         )
     }
 
-    /// Create a new RenderGroup with no GraphicsContext available
+    /// Create a new RenderGroup with no HeadlessContext available
     pub fn new_without_context(
         dims: (u32, u32),
         device: &wgpu::Device,
@@ -67,7 +81,7 @@ impl RenderGroup {
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
+                    resource: wgpu::BindingResource::Sampler(sampler),
                 },
             ],
             label: None,
@@ -97,18 +111,248 @@ impl RenderGroup {
         let s = self.texture.size();
         (s.width, s.height)
     }
+
+    /// Copy the texture back to the CPU, waiting for all submitted work to finish
+    pub async fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Rgba32FImage> {
+        let pending = self.start_read(device, queue)?.map();
+        device.poll(wgpu::Maintain::Wait);
+        pending.await
+    }
+
+    /// Submit a copy of the texture into a mappable buffer,
+    /// only Rgba32Float textures can be read
+    pub fn start_read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Readback> {
+        if self.texture.format() != wgpu::TextureFormat::Rgba32Float {
+            return Err(anyhow!(
+                "Cannot read back texture of format {:?}",
+                self.texture.format()
+            ));
+        }
+
+        // Rows copied from a texture must be padded to the copy alignment
+        let (width, height) = self.size();
+        let padded_bytes_per_row = (width * Readback::BYTES_PER_PIXEL)
+            .div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let size = padded_bytes_per_row as wgpu::BufferAddress * height as wgpu::BufferAddress;
+        let max_size = device.limits().max_buffer_size;
+        if size > max_size {
+            return Err(anyhow!(
+                "Cannot read back a {width}x{height} texture, its {size} bytes are more than the {max_size} byte buffer limit"
+            ));
+        }
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback_buf"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        // Copy the texture into the buffer
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            self.texture.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(Readback {
+            buffer,
+            size: (width, height),
+            padded_bytes_per_row,
+        })
+    }
+
+    /// Copy the texture back to the CPU as a DynamicImage
+    pub async fn read_dynamic(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<DynamicImage> {
+        Ok(DynamicImage::ImageRgba32F(self.read(device, queue).await?))
+    }
 }
 
-/// Wrapper around multiple Render Groups to ensure the correct target resolution
+/// Levels of the mip chain below each tile of the source, each half the size of the
+/// level above. Stored as floats so repeated averaging is not rounded to 8 bits
+pub struct MipChain {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+}
+
+impl MipChain {
+    /// Number of levels below a texture of the given size, halving until a single
+    /// pixel is left. A single pixel texture still has one level to bind
+    pub fn level_count((width, height): (u32, u32)) -> u32 {
+        u32::max(width, height).ilog2().max(1)
+    }
+
+    /// Create the levels for a number of tiles of the given size, filled by generate
+    pub fn new(device: &wgpu::Device, dims: (u32, u32), layers: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("mip_chain"),
+            size: wgpu::Extent3d {
+                width: u32::max(1, dims.0 / 2),
+                height: u32::max(1, dims.1 / 2),
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: MipChain::level_count(dims),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        MipChain { texture, view }
+    }
+
+    /// Create a bind group for a view, readable by textureLoad
+    fn bind(
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+        pipelines: &Pipelines,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.bind_group_layouts.rgba32float,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+            label: None,
+        })
+    }
+
+    /// Fill every level of a layer by averaging the level above, starting from the
+    /// tile. Each level is rendered into its own texture the size of the tile at that
+    /// level then copied into the chain, as reading and writing levels of the same
+    /// texture is unreliable on some backends
+    pub fn generate(
+        &self,
+        context: &HeadlessContext,
+        encoder: &mut wgpu::CommandEncoder,
+        tile: &wgpu::Texture,
+        layer: u32,
+    ) {
+        let mut source = MipChain::bind(
+            &context.device,
+            &tile.create_view(&wgpu::TextureViewDescriptor::default()),
+            &context.texture_sampler,
+            &context.pipelines,
+        );
+        for level in 0..self.texture.mip_level_count() {
+            let size = tile
+                .size()
+                .mip_level_size(level + 1, wgpu::TextureDimension::D2);
+            let group = RenderGroup::new(
+                context,
+                (size.width, size.height),
+                wgpu::TextureFormat::Rgba32Float,
+            );
+            context.render_pass(
+                encoder,
+                &context.pipelines.mipmap,
+                &group.view,
+                &[Binding(0, &source)],
+                false,
+            );
+            encoder.copy_texture_to_texture(
+                group.texture.as_image_copy(),
+                wgpu::ImageCopyTexture {
+                    texture: &self.texture,
+                    mip_level: level,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                size,
+            );
+            source = group.bind_group;
+        }
+    }
+}
+
+/// Wrapper around multiple Render Groups to ensure the correct target resolution,
+/// with the stages of each filter derived from the filter graph
 #[derive(Default)]
 pub struct RenderStages {
-    target_res: (u32, u32),
-    interpolation: Option<RenderGroup>,
-    kerneled: Option<RenderGroup>,
-    min_max: Option<RenderGroup>,
-    gamma_lut: Option<RenderGroup>,
-    gamma: Option<RenderGroup>,
+    regions: Regions,
+    display: ImageDisplay,
+    passes: Option<PassRegions>,
     output_staging: Option<RenderGroup>,
+    gamma_lut: Option<RenderGroup>,
+    nodes: Vec<NodeStage>,
+    processed: Option<RenderGroup>,
+    ranges: Vec<GlobalRange>,
+    /// Display the ranges were found with, or are being found with, None when
+    /// they need finding again
+    ranges_display: Option<ImageDisplay>,
+    /// Passes of a kernel over a region run to find the ranges so far
+    range_passes: usize,
+}
+
+/// Stages of a filter in the graph, reading the output of the filter before it
+pub enum NodeStage {
+    Kernel(Box<KernelStage>),
+    Gamma(RenderGroup),
+    Plugin(Box<PluginStage>),
+}
+
+/// Stages of a cross correlation, normalized by the min and max of its result
+pub struct KernelStage {
+    pub kernel: RenderGroup,
+    /// Row and column kernel textures, when the kernel is separable
+    pub separable_kernel: Option<(RenderGroup, RenderGroup)>,
+    /// Result of the horizontal pass of a separable kernel
+    pub separable: RenderGroup,
+    pub passes: KernelPasses,
+    pub kerneled: RenderGroup,
+    pub min_max: MinMaxStage,
+    pub normalized: RenderGroup,
+}
+
+/// Min and max of a kernel over the whole image, reduced from the min and max
+/// of each region of the image it was found in
+pub struct GlobalRange {
+    /// Min and max of each region as a texel of (min, max, min)
+    pub regions: RenderGroup,
+    pub min_max: MinMaxStage,
+}
+
+/// Stage of a filter plugin, with the uniform buffer of its parameters
+pub struct PluginStage {
+    /// File name of the plugin, the pipeline of which is run
+    pub name: String,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub output: RenderGroup,
+    /// Placement of the pass within the full image it filters
+    pub region: PassRegion,
 }
 
 impl RenderStages {
@@ -117,59 +361,501 @@ impl RenderStages {
         Self::default()
     }
 
-    /// Update the resolution of the every stage
-    pub fn update_resolution(&mut self, context: &GraphicsContext, dims: (u32, u32)) {
-        if self.target_res != dims {
-            self.interpolation = Some(RenderGroup::new(
-                context,
-                dims,
-                wgpu::TextureFormat::Rgba32Float,
-            ));
-            self.kerneled = Some(RenderGroup::new(
-                context,
-                dims,
-                wgpu::TextureFormat::Rgba32Float,
-            ));
-            self.min_max.get_or_insert_with(|| {
-                RenderGroup::new(context, (8, 8), wgpu::TextureFormat::Rgba32Float)
-            });
-            self.gamma_lut.get_or_insert_with(|| {
-                RenderGroup::new(context, (256, 1), wgpu::TextureFormat::Rgba32Float)
-            });
-            self.gamma = Some(RenderGroup::new(
-                context,
-                dims,
-                wgpu::TextureFormat::Rgba32Float,
-            ));
-            self.output_staging = Some(RenderGroup::new(
-                context,
-                dims,
-                wgpu::TextureFormat::Rgba32Float,
-            ));
+    /// Update every stage to the size of its region and the filters of the graph
+    /// which can run, only recreating the textures of a stage when its filter or
+    /// size has changed
+    pub fn update_resolution(&mut self, context: &HeadlessContext, regions: Regions) {
+        let float = wgpu::TextureFormat::Rgba32Float;
+        if self.output_staging.as_ref().map(RenderGroup::size) != Some(regions.interpolated.size) {
+            self.output_staging = Some(RenderGroup::new(context, regions.interpolated.size, float));
+        }
+        self.gamma_lut
+            .get_or_insert_with(|| RenderGroup::new(context, (256, 1), float));
+
+        // Nodes are reused by position, each filter outputs the region of its
+        // input except the kernels which output their target region
+        let mut old = mem::take(&mut self.nodes).into_iter();
+        let mut kernels = regions.kernels.iter();
+        let mut placed = (regions.interpolated, regions.scaled);
+        let mut size = regions.interpolated.size;
+        for filter in context.filters() {
+            let old = old.next();
+            let node = match filter {
+                Filter::Kernel(kernel) => {
+                    let region = kernels.next().expect("regions of every kernel");
+                    placed = (region.target, region.target_size);
+                    size = region.target.size;
+                    let mut stage = match old {
+                        Some(NodeStage::Kernel(stage))
+                            if stage.kernel.size() == kernel.size()
+                                && stage.kerneled.size() == size
+                                && stage.separable.size() == region.separable().0.size =>
+                        {
+                            stage
+                        }
+                        _ => Box::new(KernelStage::new(context, kernel.size(), region)),
+                    };
+                    stage.update(context, kernel, region);
+                    NodeStage::Kernel(stage)
+                }
+                Filter::Gamma => match old {
+                    Some(NodeStage::Gamma(output)) if output.size() == size => {
+                        NodeStage::Gamma(output)
+                    }
+                    _ => NodeStage::Gamma(RenderGroup::new(context, size, float)),
+                },
+                Filter::Plugin { name, params } => {
+                    let plugin = context.plugin(name).expect("filter of a loaded plugin");
+                    let stage = match old {
+                        Some(NodeStage::Plugin(stage))
+                            if stage.output.size() == size
+                                && stage.buffer.size() == plugin.size =>
+                        {
+                            Box::new(PluginStage {
+                                name: name.clone(),
+                                ..*stage
+                            })
+                        }
+                        _ => {
+                            let (buffer, bind_group) =
+                                plugin.create_buffer(&context.device, &context.pipelines);
+                            Box::new(PluginStage {
+                                name: name.clone(),
+                                buffer,
+                                bind_group,
+                                output: RenderGroup::new(context, size, float),
+                                region: PassRegion::new(&context.device, &context.pipelines),
+                            })
+                        }
+                    };
+                    plugins::write(&context.queue, &stage.buffer, params);
+                    stage
+                        .region
+                        .write(&context.queue, RawPassRegion::new(placed, placed));
+                    NodeStage::Plugin(stage)
+                }
+            };
+            self.nodes.push(node);
+        }
+
+        if self.processed.as_ref().map(RenderGroup::size) != Some(regions.visible.size) {
+            self.processed = Some(RenderGroup::new(context, regions.visible.size, float));
+        }
+
+        let passes = self
+            .passes
+            .get_or_insert_with(|| PassRegions::new(&context.device, &context.pipelines));
+        passes.write(&context.queue, &regions);
+        self.regions = regions;
+        self.display = context.image_display().clone();
+    }
+
+    /// Allocate the ranges of each kernel for the number of regions the image is
+    /// found in, to be found with the display from the first pass
+    pub fn start_ranges(
+        &mut self,
+        context: &HeadlessContext,
+        display: ImageDisplay,
+        kernels: usize,
+        regions: u32,
+    ) {
+        if self.ranges.first().map(|range| range.regions.size()) != Some((regions, 1)) {
+            self.ranges.clear();
         }
+        self.ranges
+            .resize_with(kernels, || GlobalRange::new(context, regions));
+        self.ranges_display = Some(display);
+        self.range_passes = 0;
     }
 
-    pub fn interpolation(&self) -> &RenderGroup {
-        &self.interpolation.as_ref().unwrap()
+    /// Min and max of each kernel over the whole image, when found or being found
+    /// with the display
+    pub fn ranges(&self, display: &ImageDisplay) -> Option<&[GlobalRange]> {
+        match self.ranges_display.as_ref() == Some(display) {
+            true => Some(&self.ranges),
+            false => None,
+        }
     }
 
-    pub fn kerneled(&self) -> &RenderGroup {
-        &self.kerneled.as_ref().unwrap()
+    /// Ranges found, which may have been found with a previous display
+    pub fn ranges_found(&self) -> &[GlobalRange] {
+        &self.ranges
     }
 
-    pub fn min_max(&self) -> &RenderGroup {
-        &self.min_max.as_ref().unwrap()
+    /// Whether ranges are being found, or have been found, with any display
+    pub fn has_ranges(&self) -> bool {
+        self.ranges_display.is_some()
     }
 
-    pub fn gamma_lut(&self) -> &RenderGroup {
-        &self.gamma_lut.as_ref().unwrap()
+    /// Passes of a kernel over a region run to find the ranges so far
+    pub fn range_passes(&self) -> usize {
+        self.range_passes
     }
 
-    pub fn gamma(&self) -> &RenderGroup {
-        &self.gamma.as_ref().unwrap()
+    /// Count passes run to find the ranges
+    pub fn run_range_passes(&mut self, passes: usize) {
+        self.range_passes += passes;
     }
 
+    /// Take the ranges found in other stages, dropping their textures
+    pub fn finish_ranges(&mut self, found: RenderStages) {
+        self.ranges = found.ranges;
+        self.ranges_display = found.ranges_display;
+        self.range_passes = found.range_passes;
+    }
+
+    /// Forget the ranges, as the image, shaders or plugins have changed
+    pub fn clear_ranges(&mut self) {
+        self.ranges.clear();
+        self.ranges_display = None;
+        self.range_passes = 0;
+    }
+
+    /// Regions the stages were last updated to
+    pub fn regions(&self) -> &Regions {
+        &self.regions
+    }
+
+    /// Display the stages were last updated with
+    pub fn display(&self) -> &ImageDisplay {
+        &self.display
+    }
+
+    /// Placement of every pass within the full images
+    pub fn passes(&self) -> &PassRegions {
+        self.passes.as_ref().unwrap()
+    }
+
+    pub fn gamma_lut(&self) -> &RenderGroup {
+        self.gamma_lut.as_ref().unwrap()
+    }
+
+    /// Result of the interpolation
     pub fn output_staging(&self) -> &RenderGroup {
-        &self.output_staging.as_ref().unwrap()
+        self.output_staging.as_ref().unwrap()
+    }
+
+    /// Stages of the filters which run, in order
+    pub fn nodes(&self) -> &[NodeStage] {
+        &self.nodes
+    }
+
+    /// Stages of the cross correlations which run, in order
+    pub fn kernels(&self) -> impl Iterator<Item = &KernelStage> {
+        self.nodes.iter().filter_map(|node| match node {
+            NodeStage::Kernel(stage) => Some(stage.as_ref()),
+            _ => None,
+        })
+    }
+
+    /// Result of the last filter converted back to linear sRGB, the final stage
+    pub fn processed(&self) -> &RenderGroup {
+        self.processed.as_ref().unwrap()
+    }
+}
+
+impl NodeStage {
+    /// Result of the filter, read by the next
+    pub fn output(&self) -> &RenderGroup {
+        match self {
+            Self::Kernel(stage) => &stage.normalized,
+            Self::Gamma(output) => output,
+            Self::Plugin(stage) => &stage.output,
+        }
+    }
+}
+
+impl KernelStage {
+    /// Create the textures of a kernel of the given size for its regions
+    pub fn new(context: &HeadlessContext, size: (u32, u32), regions: &KernelRegions) -> Self {
+        let float = wgpu::TextureFormat::Rgba32Float;
+        let target = regions.target.size;
+        let kerneled = RenderGroup::new(context, target, float);
+        KernelStage {
+            kernel: RenderGroup::new(context, size, float),
+            separable_kernel: None,
+            separable: RenderGroup::new(context, regions.separable().0.size, float),
+            passes: KernelPasses::new(&context.device, &context.pipelines),
+            min_max: MinMaxStage::new(context, &kerneled, target),
+            kerneled,
+            normalized: RenderGroup::new(context, target, float),
+        }
+    }
+
+    /// Write the kernel and the placement of its passes. Separable kernels are
+    /// also split into row and column textures for two 1D passes
+    pub fn update(&mut self, context: &HeadlessContext, kernel: &Kernel, regions: &KernelRegions) {
+        HeadlessContext::write_kernel_texture(&context.queue, &self.kernel.texture, kernel);
+        self.passes.write(&context.queue, regions);
+
+        // A single row or column is already a 1D pass
+        let size = kernel.size();
+        self.separable_kernel = match size.0 > 1 && size.1 > 1 {
+            true => kernel.separate().map(|(row, column)| {
+                let create = |kernel: &Kernel| {
+                    let group =
+                        RenderGroup::new(context, kernel.size(), wgpu::TextureFormat::Rgba32Float);
+                    HeadlessContext::write_kernel_texture(&context.queue, &group.texture, kernel);
+                    group
+                };
+                (create(&row), create(&column))
+            }),
+            false => None,
+        };
+    }
+}
+
+/// Resources for reducing the kernelled image to its min and max
+pub struct MinMaxStage {
+    /// Uniform holding the min and max as (min, max, min), bound in the normalize
+    /// stage. It is a texel of the ranges of each region when finding global ranges
+    pub result: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub passes: MinMaxPasses,
+}
+
+/// Passes of the reduction, each shrinking the previous result
+pub enum MinMaxPasses {
+    Compute {
+        /// Reduces the texture into the first buffer, with its dispatch size
+        texture_pass: (wgpu::BindGroup, (u32, u32)),
+        /// Reduces one buffer into the other, with its dispatch size
+        buffer_passes: Vec<(wgpu::BindGroup, u32)>,
+        /// Buffer holding the final min and max
+        output: wgpu::Buffer,
+    },
+    Fragment(Vec<RenderGroup>),
+}
+
+impl MinMaxStage {
+    /// Size of the block reduced by each workgroup or fragment
+    pub const BLOCK_SIZE: u32 = 16;
+
+    /// Values reduced by each workgroup of a buffer pass
+    pub const WORKGROUP_SIZE: u32 = 256;
+
+    /// Size of a single min and max pair
+    const PAIR_SIZE: u64 = 8;
+
+    /// Create the passes needed to reduce a kernelled image of the given size
+    pub fn new(context: &HeadlessContext, kerneled: &RenderGroup, dims: (u32, u32)) -> Self {
+        let device = &context.device;
+        let result = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("min_max_result"),
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &context.pipelines.min_max_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: result.as_entire_binding(),
+            }],
+            label: Some("min_max_bind_group"),
+        });
+
+        let blocks = (
+            dims.0.div_ceil(Self::BLOCK_SIZE),
+            dims.1.div_ceil(Self::BLOCK_SIZE),
+        );
+        let passes = match &context.pipelines.min_max {
+            MinMaxPipelines::Compute {
+                texture_layout,
+                buffer_layout,
+                ..
+            } => {
+                // Ping pong between two buffers large enough for the first pass
+                let pairs = (blocks.0 * blocks.1) as u64;
+                let buffers = [0, 1].map(|_| {
+                    device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some("min_max_reduction"),
+                        size: pairs * Self::PAIR_SIZE,
+                        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                        mapped_at_creation: false,
+                    })
+                });
+                let binding = |index: usize, binding, pairs: u64| wgpu::BindGroupEntry {
+                    binding,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &buffers[index],
+                        offset: 0,
+                        size: wgpu::BufferSize::new(pairs * Self::PAIR_SIZE),
+                    }),
+                };
+
+                let texture_pass = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: texture_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&kerneled.view),
+                        },
+                        binding(0, 1, pairs),
+                    ],
+                    label: Some("min_max_texture_bind_group"),
+                });
+
+                // Reduce the buffer until a single pair is left
+                let (mut pairs, mut input) = (pairs, 0);
+                let mut buffer_passes = Vec::new();
+                while pairs > 1 {
+                    let groups = pairs.div_ceil(Self::WORKGROUP_SIZE as u64);
+                    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                        layout: buffer_layout,
+                        entries: &[binding(1 - input, 1, groups), binding(input, 2, pairs)],
+                        label: Some("min_max_buffer_bind_group"),
+                    });
+                    buffer_passes.push((bind_group, groups as u32));
+                    (pairs, input) = (groups, 1 - input);
+                }
+
+                let [first, second] = buffers;
+                MinMaxPasses::Compute {
+                    texture_pass: (texture_pass, blocks),
+                    buffer_passes,
+                    output: if input == 0 { first } else { second },
+                }
+            }
+            MinMaxPipelines::Fragment(_) => {
+                // Shrink the texture by the block size until a single texel is left
+                let mut groups = vec![RenderGroup::new(
+                    context,
+                    blocks,
+                    wgpu::TextureFormat::Rgba32Float,
+                )];
+                let mut dims = blocks;
+                while dims != (1, 1) {
+                    dims = (
+                        dims.0.div_ceil(Self::BLOCK_SIZE),
+                        dims.1.div_ceil(Self::BLOCK_SIZE),
+                    );
+                    groups.push(RenderGroup::new(
+                        context,
+                        dims,
+                        wgpu::TextureFormat::Rgba32Float,
+                    ));
+                }
+                MinMaxPasses::Fragment(groups)
+            }
+        };
+
+        MinMaxStage {
+            result,
+            bind_group,
+            passes,
+        }
+    }
+}
+
+impl GlobalRange {
+    /// Create the texture holding the min and max of each region and its reduction
+    pub fn new(context: &HeadlessContext, regions: u32) -> Self {
+        let regions = RenderGroup::new(context, (regions, 1), wgpu::TextureFormat::Rgba32Float);
+        GlobalRange {
+            min_max: MinMaxStage::new(context, &regions, regions.size()),
+            regions,
+        }
+    }
+}
+
+/// Buffer holding a copy of a Rgba32Float texture waiting to be mapped
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    size: (u32, u32),
+    padded_bytes_per_row: u32,
+}
+
+/// Tiles of an image stitched together as they are read back, with the readbacks
+/// of the tiles which are still waiting to be mapped and their origins
+pub struct TiledReadback {
+    img: Rgba32FImage,
+    pending: Vec<((u32, u32), Readback)>,
+}
+
+impl TiledReadback {
+    pub fn new((width, height): (u32, u32)) -> Self {
+        TiledReadback {
+            img: Rgba32FImage::new(width, height),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Add the readback of the tile at the origin. Natively the device is waited
+    /// on and the tile copied out straight away, so only one tile is held in a
+    /// buffer at a time. The web cannot wait, so the tile is mapped with the rest
+    pub fn push(
+        &mut self,
+        device: &wgpu::Device,
+        (x, y): (u32, u32),
+        readback: Readback,
+    ) -> Result<()> {
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                let _ = device;
+                self.pending.push(((x, y), readback));
+            } else {
+                let tile = readback.map();
+                device.poll(wgpu::Maintain::Wait);
+                let tile = pollster::block_on(tile)?;
+                imageops::replace(&mut self.img, &tile, x as i64, y as i64);
+            }
+        }
+        Ok(())
+    }
+
+    /// Request every pending tile be mapped, the returned future resolves once the
+    /// device has been polled or the next submission completes
+    pub fn map(self) -> impl Future<Output = Result<Rgba32FImage>> {
+        let mut img = self.img;
+        let pending: Vec<_> = self
+            .pending
+            .into_iter()
+            .map(|(origin, readback)| (origin, readback.map()))
+            .collect();
+
+        async move {
+            for ((x, y), tile) in pending {
+                imageops::replace(&mut img, &tile.await?, x as i64, y as i64);
+            }
+            Ok(img)
+        }
+    }
+}
+
+impl Readback {
+    const BYTES_PER_PIXEL: u32 = 4 * 4;
+
+    /// Request the buffer be mapped, the returned future resolves once the device
+    /// has been polled or the next submission completes
+    pub fn map(self) -> impl Future<Output = Result<Rgba32FImage>> {
+        let (sender, receiver) = oneshot::channel();
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+
+        async move {
+            receiver.await??;
+            let (width, height) = self.size;
+            let unpadded_bytes_per_row = (width * Readback::BYTES_PER_PIXEL) as usize;
+
+            // Strip the row padding from the mapped data
+            let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+            {
+                let data = self.buffer.slice(..).get_mapped_range();
+                for row in data.chunks_exact(self.padded_bytes_per_row as usize) {
+                    let row = &row[..unpadded_bytes_per_row];
+                    pixels.extend_from_slice(bytemuck::cast_slice::<u8, f32>(row));
+                }
+            }
+            self.buffer.unmap();
+
+            Rgba32FImage::from_raw(width, height, pixels)
+                .ok_or_else(|| anyhow!("Read back buffer does not match texture size"))
+        }
     }
 }
@@ -0,0 +1,313 @@
+use std::iter;
+
+use anyhow::{anyhow, Result};
+use image::{ColorType, DynamicImage, GenericImageView};
+use wgpu::util::DeviceExt;
+
+use crate::{cpu, headless::HeadlessContext, pipelines::Pipelines, stages::MipChain};
+
+/// Layout of the tiles of the source image, matching the SourceTiles struct
+/// of uniforms.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct RawSourceTiles {
+    pub size: [i32; 2],
+    pub tile_size: [i32; 2],
+    pub columns: i32,
+    pub levels: i32,
+    pub exact_gamma: i32,
+    pub _pad: i32,
+}
+
+/// Precision the tiles of a source image are stored at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourcePrecision {
+    /// 8-bit sRGB encoded, decoded by the texture when sampled
+    Srgb8,
+    /// 16-bit sRGB encoded, decoded to linear floats when uploaded. Half floats
+    /// cannot hold every 16-bit value, so these are stored as full floats
+    Srgb16,
+    /// Linear floats from HDR and EXR images, kept unclamped
+    Float,
+}
+
+/// Source image split into tiles no larger than the device texture limit, stored
+/// as the layers of an array texture with a mip chain for every tile. Tiles are a
+/// power of two on any axis larger than the limit, so every level of the mip chain
+/// splits into tiles at the same pixels as the full image
+pub struct SourceTiles {
+    pub texture: wgpu::Texture,
+    pub mip_chain: MipChain,
+    pub bind_group: wgpu::BindGroup,
+    size: (u32, u32),
+    tile_size: (u32, u32),
+    grid: (u32, u32),
+    precision: SourcePrecision,
+}
+
+impl SourcePrecision {
+    /// Precision needed to keep every value of an image
+    pub fn of(img: &DynamicImage) -> Self {
+        match img.color() {
+            ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+                SourcePrecision::Srgb16
+            }
+            ColorType::Rgb32F | ColorType::Rgba32F => SourcePrecision::Float,
+            _ => SourcePrecision::Srgb8,
+        }
+    }
+
+    /// Format of the textures holding the tiles
+    pub fn texture_format(&self) -> wgpu::TextureFormat {
+        match self {
+            SourcePrecision::Srgb8 => wgpu::TextureFormat::Rgba8UnormSrgb,
+            SourcePrecision::Srgb16 | SourcePrecision::Float => wgpu::TextureFormat::Rgba32Float,
+        }
+    }
+
+    /// Whether gamma is computed exactly rather than from the lookup table,
+    /// which only has the precision of an 8-bit image
+    pub fn exact_gamma(&self) -> bool {
+        *self != SourcePrecision::Srgb8
+    }
+}
+
+impl SourceTiles {
+    /// Size of the tiles for an image and the number of columns and rows of tiles
+    /// covering it, a tile covers the whole axis unless it is larger than the limit
+    pub fn split((width, height): (u32, u32), limit: u32) -> ((u32, u32), (u32, u32)) {
+        let power = 1 << limit.ilog2();
+        let axis = |size: u32| match size > limit {
+            true => power,
+            false => size,
+        };
+        let tile_size = (axis(width), axis(height));
+        (
+            tile_size,
+            (width.div_ceil(tile_size.0), height.div_ceil(tile_size.1)),
+        )
+    }
+
+    /// Split the image into tiles and upload them at the precision of the image,
+    /// generating the mip chain of every tile
+    pub fn new(context: &HeadlessContext, img: &DynamicImage) -> Result<Self> {
+        let (source, tiles) = Self::upload(
+            img,
+            &context.device,
+            &context.queue,
+            &context.pipelines,
+            context.max_tile_size,
+            context.min_array_layers,
+        )?;
+
+        let mut encoder = context
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Tile Encoder"),
+            });
+        for (layer, tile) in tiles.iter().enumerate() {
+            let layer = layer as u32;
+            encoder.copy_texture_to_texture(
+                tile.as_image_copy(),
+                wgpu::ImageCopyTexture {
+                    texture: &source.texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                tile.size(),
+            );
+            source
+                .mip_chain
+                .generate(context, &mut encoder, tile, layer);
+        }
+        context.queue.submit(iter::once(encoder.finish()));
+
+        Ok(source)
+    }
+
+    /// Create new SourceTiles with no HeadlessContext available. The tiles are not
+    /// copied into their layers, so this is only a placeholder until an image is loaded
+    pub fn new_without_context(
+        img: &DynamicImage,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &Pipelines,
+        max_tile_size: u32,
+        min_array_layers: u32,
+    ) -> Result<Self> {
+        let (source, _) = Self::upload(
+            img,
+            device,
+            queue,
+            pipelines,
+            max_tile_size,
+            min_array_layers,
+        )?;
+        Ok(source)
+    }
+
+    /// Create the textures and upload every tile into its own texture, returned to be
+    /// copied into its layer
+    fn upload(
+        img: &DynamicImage,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &Pipelines,
+        max_tile_size: u32,
+        min_array_layers: u32,
+    ) -> Result<(Self, Vec<wgpu::Texture>)> {
+        let size = img.dimensions();
+        let (tile_size, (columns, rows)) = SourceTiles::split(size, max_tile_size);
+        let max_layers = device.limits().max_texture_array_layers;
+        if columns * rows > max_layers {
+            return Err(anyhow!(
+                "Image of {}x{} needs {} tiles, more than the {max_layers} supported by the device",
+                size.0,
+                size.1,
+                columns * rows,
+            ));
+        }
+
+        // 8-bit images are decoded by the texture, others are decoded to linear floats
+        let precision = SourcePrecision::of(img);
+        let format = precision.texture_format();
+        let data = match precision {
+            SourcePrecision::Srgb8 => img.to_rgba8().into_raw(),
+            _ => bytemuck::cast_slice(cpu::to_linear(img).as_raw()).to_vec(),
+        };
+        let pixel_bytes = format.block_size(None).unwrap_or(4);
+
+        // GL creates square textures with a multiple of six layers as cube maps,
+        // which cannot be bound as arrays, so an unused layer is added
+        let mut layers = u32::max(columns * rows, min_array_layers);
+        if tile_size.0 == tile_size.1 && layers % 6 == 0 {
+            layers += 1;
+        }
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("source_tiles"),
+            size: wgpu::Extent3d {
+                width: tile_size.0,
+                height: tile_size.1,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let mut tiles = Vec::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let origin = (column * tile_size.0, row * tile_size.1);
+                let extent = wgpu::Extent3d {
+                    width: u32::min(tile_size.0, size.0 - origin.0),
+                    height: u32::min(tile_size.1, size.1 - origin.1),
+                    depth_or_array_layers: 1,
+                };
+                let tile = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some("source_tile"),
+                    size: extent,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC
+                        | wgpu::TextureUsages::COPY_DST,
+                    view_formats: &[],
+                });
+                let offset = pixel_bytes as usize
+                    * (origin.1 as usize * size.0 as usize + origin.0 as usize);
+                queue.write_texture(
+                    tile.as_image_copy(),
+                    &data[offset..],
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(pixel_bytes * size.0),
+                        rows_per_image: Some(extent.height),
+                    },
+                    extent,
+                );
+                tiles.push(tile);
+            }
+        }
+
+        let mip_chain = MipChain::new(device, tile_size, layers);
+        let raw = RawSourceTiles {
+            size: [size.0 as i32, size.1 as i32],
+            tile_size: [tile_size.0 as i32, tile_size.1 as i32],
+            columns: columns as i32,
+            levels: MipChain::level_count(tile_size) as i32 + 1,
+            exact_gamma: precision.exact_gamma() as i32,
+            ..Default::default()
+        };
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("source_tiles_buf"),
+            contents: bytemuck::bytes_of(&raw),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let array = |texture: &wgpu::Texture| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                ..Default::default()
+            })
+        };
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &pipelines.source_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&array(&texture)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&mip_chain.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("source_bind_group"),
+        });
+
+        let source = SourceTiles {
+            texture,
+            mip_chain,
+            bind_group,
+            size,
+            tile_size,
+            grid: (columns, rows),
+            precision,
+        };
+        Ok((source, tiles))
+    }
+
+    /// Get the size of the full image
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Get the size of each tile
+    pub fn tile_size(&self) -> (u32, u32) {
+        self.tile_size
+    }
+
+    /// Get the number of columns and rows of tiles
+    pub fn grid(&self) -> (u32, u32) {
+        self.grid
+    }
+
+    /// Get the precision the tiles are stored at
+    pub fn precision(&self) -> SourcePrecision {
+        self.precision
+    }
+}
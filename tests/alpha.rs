@@ -0,0 +1,152 @@
+mod common;
+
+use cs256::{
+    cpu,
+    graph::FilterGraph,
+    image_display::{ImageDisplay, ScalingMode},
+    kernel::Kernel,
+};
+use image::{DynamicImage, Rgba, Rgba32FImage, RgbaImage};
+
+/// Lena with bands of transparent, translucent and opaque pixels. Transparent
+/// pixels are green, which should never bleed into their neighbours
+fn translucent_lena() -> DynamicImage {
+    let lena = common::lena().to_rgba8();
+    let img = RgbaImage::from_fn(lena.width(), lena.height(), |x, y| {
+        let p = lena.get_pixel(x, y);
+        match (x / 8 + y / 8) % 3 {
+            0 => Rgba([0, 255, 0, 0]),
+            1 => Rgba([p[0], p[1], p[2], 128]),
+            _ => *p,
+        }
+    });
+    DynamicImage::ImageRgba8(img)
+}
+
+/// Check every channel of two images is within the tolerance, after premultiplying
+/// as the colour of a nearly transparent pixel is not visible
+fn assert_premultiplied_close(gpu: &Rgba32FImage, cpu: &Rgba32FImage, tolerance: f32, case: &str) {
+    assert_eq!(gpu.dimensions(), cpu.dimensions(), "{case}");
+    let (gpu, cpu) = (cpu::premultiply(gpu), cpu::premultiply(cpu));
+    for (x, y, p) in gpu.enumerate_pixels() {
+        let q = cpu.get_pixel(x, y);
+        for i in 0..4 {
+            assert!(
+                (p[i] - q[i]).abs() <= tolerance,
+                "{case} at ({x}, {y}): {p:?} != {q:?}"
+            );
+        }
+    }
+}
+
+/// Check the GPU keeps alpha the same as the CPU reference for every display
+fn check_displays(displays: impl IntoIterator<Item = ImageDisplay>) {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let source = translucent_lena();
+    context.load_image(&source).unwrap();
+
+    for display in displays {
+        let case = format!(
+            "{:?} {} process alpha {}",
+            display.scaling_mode, display.size, display.process_alpha
+        );
+        let tolerance = cpu::tolerance(display.gamma);
+        let (gpu, cpu) = common::process(&mut context, &source, display);
+        assert_premultiplied_close(&gpu, &cpu, tolerance, &case);
+    }
+}
+
+#[test]
+fn downsample_weights_alpha() {
+    // Opaque red beside transparent green averages to translucent red
+    let img = Rgba32FImage::from_fn(2, 2, |x, _| match x {
+        0 => Rgba([1.0, 0.0, 0.0, 1.0]),
+        _ => Rgba([0.0, 1.0, 0.0, 0.0]),
+    });
+    assert_eq!(
+        cpu::downsample(&img).get_pixel(0, 0).0,
+        [1.0, 0.0, 0.0, 0.5]
+    );
+
+    // A fully transparent block has no colour
+    let img = Rgba32FImage::from_pixel(2, 2, Rgba([0.3, 0.6, 0.9, 0.0]));
+    assert_eq!(cpu::downsample(&img).get_pixel(0, 0).0, [0.0; 4]);
+}
+
+#[test]
+fn no_colour_bleeding() {
+    // Half opaque red and half transparent green
+    let img = Rgba32FImage::from_fn(8, 8, |x, _| match x < 4 {
+        true => Rgba([1.0, 0.0, 0.0, 1.0]),
+        false => Rgba([0.0, 1.0, 0.0, 0.0]),
+    });
+    for scaling_mode in ScalingMode::ALL {
+        for size in [2.3, 0.45] {
+            let display = ImageDisplay {
+                scaling_mode,
+                size,
+                ..Default::default()
+            };
+            let scaled = cpu::interpolate(img.clone(), &display);
+            for p in scaled.pixels() {
+                assert!(p[1].abs() < 1e-5, "{scaling_mode:?} {size}: {p:?}");
+            }
+            // Filtering across the edge blends the alpha
+            if scaling_mode != ScalingMode::NearestNeighbour {
+                assert!(
+                    scaled.pixels().any(|p| p[3] > 0.1 && p[3] < 0.9),
+                    "{scaling_mode:?} {size}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn interpolation() {
+    check_displays(ScalingMode::ALL.into_iter().flat_map(|scaling_mode| {
+        [1.7, 0.6].map(|size| ImageDisplay {
+            scaling_mode,
+            size,
+            ..Default::default()
+        })
+    }));
+}
+
+#[test]
+fn process_alpha() {
+    check_displays([false, true].map(|process_alpha| ImageDisplay {
+        scaling_mode: ScalingMode::Bilinear,
+        size: 0.8,
+        gamma: 1.4,
+        filters: FilterGraph::standard(Some(Kernel::laplacian())),
+        process_alpha,
+        ..Default::default()
+    }));
+
+    // Alpha passes through the kernel, normalization and gamma unless processed
+    let source = translucent_lena();
+    let display = ImageDisplay {
+        filters: FilterGraph::standard(Some(Kernel::laplacian())),
+        gamma: 1.4,
+        ..Default::default()
+    };
+    let interpolated = cpu::interpolate(cpu::to_linear(&source), &display);
+    let processed = cpu::process(&source, &display);
+    for (p, q) in processed.pixels().zip(interpolated.pixels()) {
+        assert_eq!(p[3], q[3]);
+    }
+
+    // Processed, the laplacian of the alpha bands is no longer the source alpha
+    let display = ImageDisplay {
+        process_alpha: true,
+        ..display
+    };
+    let processed = cpu::process(&source, &display);
+    assert!(processed
+        .pixels()
+        .zip(interpolated.pixels())
+        .any(|(p, q)| (p[3] - q[3]).abs() > 0.1));
+}
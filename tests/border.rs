@@ -0,0 +1,102 @@
+mod common;
+
+use cs256::{
+    cpu::{self, Border},
+    graph::FilterGraph,
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+};
+use image::{Rgba, Rgba32FImage};
+
+/// Colour used for the constant border mode
+const BORDER_COLOUR: [f32; 4] = [0.2, 0.6, 0.9, 1.0];
+
+/// Kernel which is not separable
+fn full_kernel() -> Kernel {
+    Kernel::laplacian()
+}
+
+/// Kernel which is separable, with a different width and height
+fn separable_kernel() -> Kernel {
+    let weights = [1.0, 2.0, 1.0]
+        .iter()
+        .flat_map(|c| [-1.0, -2.0, 0.0, 2.0, 1.0].map(|r| c * r))
+        .collect();
+    Kernel::new((5, 3), weights).unwrap()
+}
+
+/// Check the GPU matches the CPU reference for every border mode
+fn check_border_modes(asset: &str, scaling_mode: ScalingMode, size: f32, kernel: Option<Kernel>) {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let source = common::asset(asset);
+    context.load_image(&source).unwrap();
+
+    for border_mode in BorderMode::ALL {
+        let display = ImageDisplay {
+            scaling_mode,
+            size,
+            filters: FilterGraph::standard(kernel.clone()),
+            border_mode,
+            border_colour: BORDER_COLOUR,
+            ..Default::default()
+        };
+        common::gpu_matches_cpu(&mut context, &source, display, &format!("{border_mode:?}"));
+    }
+}
+
+#[test]
+fn bilinear_borders() {
+    check_border_modes("square.png", ScalingMode::Bilinear, 2.5, None);
+}
+
+#[test]
+fn full_kernel_borders() {
+    check_border_modes(
+        "lena.png",
+        ScalingMode::NearestNeighbour,
+        0.125,
+        Some(full_kernel()),
+    );
+}
+
+#[test]
+fn separable_kernel_borders() {
+    check_border_modes(
+        "lena.png",
+        ScalingMode::NearestNeighbour,
+        0.125,
+        Some(separable_kernel()),
+    );
+}
+
+#[test]
+fn border_sampling() {
+    // A 3x1 ramp, the kernel picks the pixel two to the left
+    let img = Rgba32FImage::from_fn(3, 1, |x, _| Rgba([x as f32, 0.0, 0.0, 1.0]));
+    let kernel = Kernel::new((5, 1), vec![1.0, 0.0, 0.0, 0.0, 0.0]).unwrap();
+    let shifted = |mode| {
+        let border = Border {
+            mode,
+            colour: BORDER_COLOUR,
+        };
+        let kerneled = cpu::apply_kernel(&img, &kernel, border, false);
+        kerneled.pixels().map(|p| p[0]).collect::<Vec<f32>>()
+    };
+
+    assert_eq!(shifted(BorderMode::Clamp), [0.0, 0.0, 0.0]);
+    assert_eq!(shifted(BorderMode::Mirror), [1.0, 0.0, 0.0]);
+    assert_eq!(shifted(BorderMode::Wrap), [1.0, 2.0, 0.0]);
+    assert_eq!(shifted(BorderMode::Constant), [0.2, 0.2, 0.0]);
+
+    // Only the centre of a 5 wide image has its kernel inside the image
+    let img = Rgba32FImage::from_fn(5, 1, |x, _| Rgba([x as f32, 0.0, 0.0, 1.0]));
+    let border = Border {
+        mode: BorderMode::Valid,
+        ..Default::default()
+    };
+    let kerneled = cpu::apply_kernel(&img, &kernel, border, false);
+    assert_eq!(kerneled.dimensions(), (1, 1));
+    assert_eq!(kerneled.get_pixel(0, 0)[0], 0.0);
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use std::{path::PathBuf, process::Command};
+
+/// Run the CLI binary with arguments, returning whether it succeeded and its stderr
+fn run(args: &[String]) -> (bool, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_cs256-cli"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+/// Inputs with the same file stem are rejected before anything is processed
+#[test]
+fn duplicate_outputs() {
+    let dir = std::env::temp_dir().join(format!("cs256-cli-{}", std::process::id()));
+    let out = dir.join("out");
+    for sub in ["a", "b"] {
+        std::fs::create_dir_all(dir.join(sub)).unwrap();
+    }
+    let asset = common::asset_path("square.png");
+    let inputs = [
+        dir.join("a").join("x.png"),
+        dir.join("b").join("x.png"),
+        dir.join("a").join("x.jpg"),
+    ];
+    for input in &inputs {
+        std::fs::copy(&asset, input).unwrap();
+    }
+    let path = |p: &PathBuf| p.to_str().unwrap().to_owned();
+
+    for pair in [[0, 1], [0, 2]] {
+        let args = [
+            path(&inputs[pair[0]]),
+            path(&inputs[pair[1]]),
+            "--output".to_owned(),
+            path(&out),
+            "--backend".to_owned(),
+            "cpu".to_owned(),
+        ];
+        let (success, stderr) = run(&args);
+        assert!(!success);
+        assert!(stderr.contains("would both be written to"), "{stderr}");
+        assert!(!out.exists());
+    }
+
+    // Different stems are written side by side
+    let args = [
+        path(&inputs[0]),
+        path(&asset),
+        "--output".to_owned(),
+        path(&out),
+        "--backend".to_owned(),
+        "cpu".to_owned(),
+    ];
+    let (success, stderr) = run(&args);
+    assert!(success, "{stderr}");
+    assert!(out.join("x.png").exists() && out.join("square.png").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
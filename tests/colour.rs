@@ -0,0 +1,203 @@
+mod common;
+
+use cs256::{
+    colour::{self, WorkingSpace},
+    cpu,
+    graph::FilterGraph,
+    headless::HeadlessContext,
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+    shaders,
+};
+use image::{DynamicImage, Rgba, Rgba32FImage};
+
+/// Linear sRGB colours, including values outside of 0..1
+const COLOURS: [[f32; 4]; 5] = [
+    [0.0, 0.0, 0.0, 1.0],
+    [1.0, 1.0, 1.0, 0.5],
+    [0.002, 0.18, 0.9, 1.0],
+    [1.0, 0.0, 0.0, 1.0],
+    [-0.2, 2.5, 0.4, 1.0],
+];
+
+fn assert_close(a: [f32; 4], b: [f32; 4], tolerance: f32, case: &str) {
+    for i in 0..4 {
+        assert!((a[i] - b[i]).abs() <= tolerance, "{case}: {a:?} != {b:?}");
+    }
+}
+
+/// Render a source in every working space, returning the GPU and CPU results
+fn render(
+    context: &mut HeadlessContext,
+    source: &DynamicImage,
+    display: ImageDisplay,
+) -> Vec<(WorkingSpace, Rgba32FImage, Rgba32FImage)> {
+    context.load_image(source).unwrap();
+    WorkingSpace::ALL
+        .into_iter()
+        .map(|working_space| {
+            *context.image_display_mut() = ImageDisplay {
+                working_space,
+                ..display.clone()
+            };
+            context.render();
+            let gpu = pollster::block_on(context.read_output())
+                .unwrap()
+                .into_rgba32f();
+            let cpu = cpu::process(source, context.image_display());
+            (working_space, gpu, cpu)
+        })
+        .collect()
+}
+
+#[test]
+fn round_trip() {
+    for space in WorkingSpace::ALL {
+        for c in COLOURS {
+            let converted = space.from_linear_srgb(c);
+            assert_close(space.to_linear_srgb(converted), c, 1e-4, space.name());
+        }
+        // Alpha is never converted
+        assert_eq!(space.from_linear_srgb(COLOURS[1])[3], 0.5);
+    }
+}
+
+#[test]
+fn white_point() {
+    // White is the same in every working space, and encoding leaves it unchanged
+    for space in WorkingSpace::ALL {
+        let white = space.from_linear_srgb([1.0; 4]);
+        assert_close(white, [1.0; 4], 1e-4, space.name());
+    }
+    assert_eq!(colour::encode(0.0), 0.0);
+    assert!((colour::encode(0.18) - 0.461).abs() < 1e-3);
+    assert_eq!(colour::decode(-0.5), -colour::decode(0.5));
+}
+
+#[test]
+fn filtering_space() {
+    // Black beside white, the bilinear blend a quarter of the way across
+    // is linear in the working space
+    let img = Rgba32FImage::from_fn(2, 1, |x, _| Rgba([x as f32, x as f32, x as f32, 1.0]));
+    let source = DynamicImage::ImageRgba32F(img);
+    let blend = |working_space| {
+        let display = ImageDisplay {
+            scaling_mode: ScalingMode::Bilinear,
+            size: 4.0,
+            working_space,
+            ..Default::default()
+        };
+        cpu::process(&source, &display).get_pixel(3, 0)[0]
+    };
+    assert!((blend(WorkingSpace::LinearSrgb) - 0.375).abs() < 1e-5);
+    assert!((blend(WorkingSpace::Srgb) - colour::decode(0.375)).abs() < 1e-5);
+}
+
+#[test]
+fn conversions_cancel() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+
+    // A float source has exact gamma, so without any processing
+    // the output is the source whatever the working space
+    let img = Rgba32FImage::from_fn(5, 1, |x, _| Rgba(COLOURS[x as usize]));
+    let source = DynamicImage::ImageRgba32F(img.clone());
+    for (space, gpu, cpu) in render(&mut context, &source, ImageDisplay::default()) {
+        for ((p, q), c) in gpu.pixels().zip(cpu.pixels()).zip(img.pixels()) {
+            assert_close(p.0, c.0, 1e-4, space.name());
+            assert_close(q.0, c.0, 1e-4, space.name());
+        }
+    }
+}
+
+#[test]
+fn gpu_matches_cpu() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let display = ImageDisplay {
+        scaling_mode: ScalingMode::Bilinear,
+        size: 0.7,
+        gamma: 1.3,
+        filters: FilterGraph::standard(Some(Kernel::laplacian())),
+        border_mode: BorderMode::Constant,
+        border_colour: [0.8, 0.1, 0.3, 1.0],
+        ..Default::default()
+    };
+
+    // Decoding or converting the primaries after the gamma lookup can
+    // magnify the difference by the largest slope of the conversion
+    let tolerance = cpu::tolerance(display.gamma) * 2.5;
+    for (space, gpu, cpu) in render(&mut context, &common::lena(), display) {
+        assert_eq!(gpu.dimensions(), cpu.dimensions());
+        for (x, y, p) in gpu.enumerate_pixels() {
+            let case = format!("{} at ({x}, {y})", space.name());
+            assert_close(p.0, cpu.get_pixel(x, y).0, tolerance, &case);
+        }
+    }
+}
+
+/// Every matrix literal in an embedded shader as rows, in the order they appear
+fn shader_matrices(name: &str) -> Vec<[[f32; 3]; 3]> {
+    let source = shaders::embedded(name).unwrap();
+    source
+        .split("mat3x3<f32>(")
+        .skip(1)
+        .map(|rest| {
+            let values: Vec<f32> = rest[..rest.find(')').unwrap()]
+                .split(',')
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse().unwrap())
+                .collect();
+            assert_eq!(values.len(), 9, "{name}: {values:?}");
+            std::array::from_fn(|row| std::array::from_fn(|col| values[row * 3 + col]))
+        })
+        .collect()
+}
+
+/// The matrices of a conversion as rows, found from the images of the basis vectors.
+/// Encoded results are decoded, leaving only the change of primaries
+fn rust_matrix(convert: impl Fn([f32; 4]) -> [f32; 4], encoded: bool) -> [[f32; 3]; 3] {
+    let columns: [[f32; 4]; 3] = std::array::from_fn(|col| {
+        let mut basis = [0.0, 0.0, 0.0, 1.0];
+        basis[col] = 1.0;
+        convert(basis).map(|c| match encoded {
+            true => colour::decode(c),
+            false => c,
+        })
+    });
+    std::array::from_fn(|row| std::array::from_fn(|col| columns[col][row]))
+}
+
+#[test]
+fn shader_matrices_match() {
+    let spaces = [WorkingSpace::DisplayP3, WorkingSpace::AcesCg];
+    let to_linear = shader_matrices("convert.wgsl");
+    let from_linear = shader_matrices("interpolation.wgsl");
+    assert_eq!((to_linear.len(), from_linear.len()), (2, 2));
+
+    for (i, space) in spaces.into_iter().enumerate() {
+        // Basis vectors are unchanged by the transfer curve applied before the matrix
+        let expected = [
+            (
+                to_linear[i],
+                rust_matrix(|c| space.to_linear_srgb(c), false),
+            ),
+            (
+                from_linear[i],
+                rust_matrix(|c| space.from_linear_srgb(c), space.encoded()),
+            ),
+        ];
+        for (wgsl, rust) in expected {
+            for (a, b) in wgsl.iter().flatten().zip(rust.iter().flatten()) {
+                assert!(
+                    (a - b).abs() <= 1e-5,
+                    "{}: {wgsl:?} != {rust:?}",
+                    space.name()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+// Setup shared by the integration tests, not every test uses all of it
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use cs256::{cpu, headless::HeadlessContext, image_display::ImageDisplay};
+use image::{DynamicImage, Rgba32FImage};
+
+/// Set to skip the GPU tests on machines without an adapter instead of failing them
+pub const ALLOW_NO_GPU_VAR: &str = "CS256_ALLOW_NO_GPU";
+
+/// Create a headless context on the fallback adapter. Without an adapter the test
+/// fails, unless CS256_ALLOW_NO_GPU is set and it is skipped with a message
+pub fn gpu() -> Option<HeadlessContext> {
+    match pollster::block_on(HeadlessContext::new(true)) {
+        Ok(context) => Some(context),
+        Err(e) => match std::env::var_os(ALLOW_NO_GPU_VAR).is_some() {
+            true => {
+                eprintln!("Skipping GPU test as {ALLOW_NO_GPU_VAR} is set: {e:#}");
+                None
+            }
+            false => panic!("No GPU adapter, set {ALLOW_NO_GPU_VAR} to skip GPU tests: {e:#}"),
+        },
+    }
+}
+
+/// Process a source with the display on the GPU and with the CPU reference,
+/// returning the GPU result then the CPU one
+pub fn process(
+    context: &mut HeadlessContext,
+    source: &DynamicImage,
+    display: ImageDisplay,
+) -> (Rgba32FImage, Rgba32FImage) {
+    *context.image_display_mut() = display;
+    context.image_display.set_changed();
+    let gpu = pollster::block_on(context.read_output())
+        .unwrap()
+        .into_rgba32f();
+    let cpu = cpu::process(source, context.image_display());
+    (gpu, cpu)
+}
+
+/// Check every colour channel of two images is within the tolerance
+pub fn assert_close(gpu: &Rgba32FImage, cpu: &Rgba32FImage, tolerance: f32, case: &str) {
+    assert_eq!(gpu.dimensions(), cpu.dimensions(), "{case}");
+    for (x, y, p) in gpu.enumerate_pixels() {
+        let q = cpu.get_pixel(x, y);
+        for i in 0..3 {
+            assert!(
+                (p[i] - q[i]).abs() <= tolerance,
+                "{case} at ({x}, {y}): {p:?} != {q:?}"
+            );
+        }
+    }
+}
+
+/// Check the GPU matches the CPU reference for a display, within the
+/// tolerance of its gamma
+pub fn gpu_matches_cpu(
+    context: &mut HeadlessContext,
+    source: &DynamicImage,
+    display: ImageDisplay,
+    case: &str,
+) {
+    let tolerance = cpu::tolerance(display.gamma);
+    let (gpu, cpu) = process(context, source, display);
+    assert_close(&gpu, &cpu, tolerance, case);
+}
+
+/// Path of a file in the assets directory
+pub fn asset_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("assets")
+        .join(name)
+}
+
+/// Load an image from the assets directory
+pub fn asset(name: &str) -> DynamicImage {
+    image::open(asset_path(name)).unwrap()
+}
+
+/// Load the standard test image
+pub fn lena() -> DynamicImage {
+    asset("lena.png")
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use cs256::{cpu, export::ExportFormat, graph::FilterGraph, image_display::ImageDisplay};
+use image::DynamicImage;
+
+/// An image at scale 1 without any filters is exported with the pixels of its source
+#[test]
+fn unprocessed_round_trip() {
+    let display = ImageDisplay {
+        filters: FilterGraph { nodes: Vec::new() },
+        ..Default::default()
+    };
+    for name in ["square.png", "dirt.png", "lena.png"] {
+        let source = common::asset(name);
+        let output = DynamicImage::ImageRgba32F(cpu::process(&source, &display));
+        let expected = source.to_rgba8();
+
+        for format in [ExportFormat::Png8, ExportFormat::Tiff8, ExportFormat::WebP] {
+            let bytes = format.encode(&output).unwrap();
+            let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+            assert_eq!(decoded.dimensions(), expected.dimensions());
+            for (x, y, p) in decoded.enumerate_pixels() {
+                let q = expected.get_pixel(x, y);
+                for i in 0..4 {
+                    assert!(
+                        p[i].abs_diff(q[i]) <= 1,
+                        "{name} as {} at ({x}, {y}): {p:?} != {q:?}",
+                        format.name()
+                    );
+                }
+            }
+        }
+
+        // Sixteen bit formats keep the precision of the encoded source
+        let bytes = ExportFormat::Png16.encode(&output).unwrap();
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert_eq!(decoded, expected, "{name} as 16-bit PNG");
+    }
+}
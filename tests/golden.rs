@@ -0,0 +1,159 @@
+mod common;
+
+use std::path::PathBuf;
+
+use cs256::{
+    cpu,
+    export::ExportFormat,
+    graph::FilterGraph,
+    image_display::{ImageDisplay, ScalingMode},
+    kernel::Kernel,
+};
+use image::{DynamicImage, Rgba32FImage, RgbaImage};
+
+/// Minimum peak signal to noise ratio between the GPU and CPU results
+const MIN_PSNR: f64 = 40.0;
+
+/// Settings every asset is rendered with
+const CASES: &[(&str, ScalingMode, f32, f32, bool)] = &[
+    ("nearest", ScalingMode::NearestNeighbour, 1.0, 1.0, false),
+    ("bilinear", ScalingMode::Bilinear, 1.5, 0.7, false),
+    ("downscale", ScalingMode::Bilinear, 0.6, 1.4, false),
+    ("laplacian", ScalingMode::NearestNeighbour, 1.0, 1.2, true),
+];
+
+/// Create a display with the settings of a case
+fn display(
+    &(_, scaling_mode, size, gamma, cross_correlation): &(&str, ScalingMode, f32, f32, bool),
+) -> ImageDisplay {
+    ImageDisplay {
+        scaling_mode,
+        size,
+        gamma,
+        filters: FilterGraph::standard(cross_correlation.then(Kernel::laplacian)),
+        ..Default::default()
+    }
+}
+
+/// Largest per channel difference and the PSNR over the colour channels
+fn compare(a: &Rgba32FImage, b: &Rgba32FImage) -> (f32, f64) {
+    assert_eq!(a.dimensions(), b.dimensions());
+    let (mut max, mut squared) = (0.0f32, 0.0f64);
+    for (p, q) in a.pixels().zip(b.pixels()) {
+        for i in 0..3 {
+            let d = (p[i] - q[i]).abs();
+            max = max.max(d);
+            squared += (d as f64).powi(2);
+        }
+    }
+    let mse = squared / (a.len() / 4 * 3) as f64;
+    (max, 10.0 * (1.0 / mse).log10())
+}
+
+/// Render every case through the GPU and compare against the CPU reference
+fn check_asset(name: &str) {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let source = common::asset(name);
+    context.load_image(&source).unwrap();
+
+    for case in CASES {
+        let (gpu, cpu) = common::process(&mut context, &source, display(case));
+        let (max, psnr) = compare(&gpu, &cpu);
+
+        let tolerance = cpu::tolerance(case.3);
+        assert!(
+            max <= tolerance,
+            "{name} {}: difference {max} exceeds {tolerance}",
+            case.0
+        );
+        assert!(
+            psnr >= MIN_PSNR,
+            "{name} {}: PSNR {psnr} below {MIN_PSNR}",
+            case.0
+        );
+    }
+}
+
+/// Compare the CPU reference, exported as an 8-bit PNG, against checked in golden
+/// images, set UPDATE_GOLDEN to regenerate them
+fn cpu_matches_golden(name: &str) {
+    let source = common::asset(name);
+    let golden_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden");
+
+    for case in CASES {
+        let output = DynamicImage::ImageRgba32F(cpu::process(&source, &display(case)));
+        let bytes = ExportFormat::Png8.encode(&output).unwrap();
+        let stem = name.split('.').next().unwrap();
+        let path = golden_dir.join(format!("{stem}_{}.png", case.0));
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::create_dir_all(&golden_dir).unwrap();
+            std::fs::write(&path, bytes).unwrap();
+            continue;
+        }
+
+        let output: RgbaImage = image::load_from_memory(&bytes).unwrap().to_rgba8();
+
+        let golden: RgbaImage = image::open(&path).unwrap().to_rgba8();
+        assert_eq!(
+            output.dimensions(),
+            golden.dimensions(),
+            "{}",
+            path.display()
+        );
+        for (x, y, p) in output.enumerate_pixels() {
+            let q = golden.get_pixel(x, y);
+            for i in 0..4 {
+                assert!(
+                    p[i].abs_diff(q[i]) <= 1,
+                    "{} differs at ({x}, {y}): {p:?} != {q:?}",
+                    path.display()
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn lena_gpu() {
+    check_asset("lena.png");
+}
+
+#[test]
+fn raytrace_gpu() {
+    check_asset("raytrace.jpg");
+}
+
+#[test]
+fn dirt_gpu() {
+    check_asset("dirt.png");
+}
+
+#[test]
+fn square_gpu() {
+    check_asset("square.png");
+}
+
+#[test]
+fn lena_golden() {
+    cpu_matches_golden("lena.png");
+}
+
+#[test]
+fn raytrace_golden() {
+    cpu_matches_golden("raytrace.jpg");
+}
+
+#[test]
+fn dirt_golden() {
+    cpu_matches_golden("dirt.png");
+}
+
+#[test]
+fn square_golden() {
+    cpu_matches_golden("square.png");
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use cs256::{
+    cpu,
+    graph::{Filter, FilterGraph, FilterNode},
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+};
+use image::Rgba32FImage;
+
+/// Box blur of a given size, separable with every weight equal
+fn blur((width, height): (u32, u32)) -> Kernel {
+    let weights = vec![1.0; (width * height) as usize];
+    Kernel::new((width, height), weights).unwrap()
+}
+
+/// Graph of kernels in series followed by gamma correction
+fn series(kernels: &[Kernel]) -> FilterGraph {
+    let mut nodes: Vec<_> = kernels
+        .iter()
+        .map(|kernel| FilterNode::new(Filter::Kernel(kernel.clone()), true))
+        .collect();
+    nodes.push(FilterNode::new(Filter::Gamma, true));
+    FilterGraph { nodes }
+}
+
+#[test]
+fn editing() {
+    let mut graph = series(&[Kernel::laplacian(), blur((3, 1))]);
+    graph.move_up(1);
+    assert_eq!(graph.nodes[0].filter, Filter::Kernel(blur((3, 1))));
+    graph.move_down(1);
+    assert_eq!(graph.nodes[2].filter, Filter::Kernel(Kernel::laplacian()));
+
+    // Moving past either end leaves the graph unchanged
+    let unchanged = graph.clone();
+    graph.move_up(0);
+    graph.move_down(2);
+    assert_eq!(graph, unchanged);
+
+    graph.duplicate(0);
+    graph.nodes[1].enabled = false;
+    assert_eq!(graph.nodes.len(), 4);
+    assert_eq!(graph.kernels().count(), 2);
+    graph.remove(3);
+    assert_eq!(
+        graph.enabled().collect::<Vec<_>>(),
+        [&Filter::Kernel(blur((3, 1))), &Filter::Gamma]
+    );
+
+    // The standard graph only enables the kernel when there is one
+    assert_eq!(FilterGraph::standard(None).kernels().count(), 0);
+    assert_eq!(
+        FilterGraph::standard(Some(blur((3, 3)))).kernels().count(),
+        1
+    );
+}
+
+#[test]
+fn kernels_in_series() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let source = common::lena();
+    context.load_image(&source).unwrap();
+
+    // A blur then a different sized kernel, each cropping the valid border
+    let kernels = [blur((5, 3)), Kernel::laplacian()];
+    for border_mode in [BorderMode::Clamp, BorderMode::Valid] {
+        *context.image_display_mut() = ImageDisplay {
+            scaling_mode: ScalingMode::Bilinear,
+            size: 0.3,
+            gamma: 1.2,
+            filters: series(&kernels),
+            border_mode,
+            ..Default::default()
+        };
+        context.render();
+
+        // A stage is allocated for each kernel, at the size of its result
+        let regions = context.stages.regions().clone();
+        assert_eq!(regions.kernels.len(), 2);
+        let sizes: Vec<_> = context
+            .stages
+            .kernels()
+            .map(|k| k.kerneled.size())
+            .collect();
+        let targets: Vec<_> = regions.kernels.iter().map(|k| k.target.size).collect();
+        assert_eq!(sizes, targets);
+        assert_eq!(context.stages.nodes().len(), 3);
+
+        let gpu: Rgba32FImage = pollster::block_on(context.read_output())
+            .unwrap()
+            .into_rgba32f();
+        let cpu = cpu::process(&source, context.image_display());
+        assert_eq!(gpu.dimensions(), cpu.dimensions());
+        if border_mode == BorderMode::Valid {
+            let (width, height) = context.scaled_texture_size();
+            let crop = kernels.iter().map(|k| border_mode.crop(k.size()));
+            let crop = crop.fold((0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+            assert_eq!(gpu.dimensions(), (width - crop.0, height - crop.1));
+        }
+
+        let tolerance = cpu::tolerance(1.2);
+        for (x, y, p) in gpu.enumerate_pixels() {
+            let q = cpu.get_pixel(x, y);
+            for i in 0..4 {
+                assert!(
+                    (p[i] - q[i]).abs() <= tolerance,
+                    "{border_mode:?} at ({x}, {y}): {p:?} != {q:?}"
+                );
+            }
+        }
+    }
+}
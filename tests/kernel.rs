@@ -0,0 +1,168 @@
+mod common;
+
+use cs256::{
+    cpu,
+    graph::FilterGraph,
+    image_display::{ImageDisplay, ScalingMode},
+    kernel::Kernel,
+};
+use image::Rgba32FImage;
+
+/// Create a kernel of the given size with distinct weights, so a transposed or
+/// offset kernel gives a different result
+fn kernel((width, height): (u32, u32)) -> Kernel {
+    let weights = (0..width * height)
+        .map(|i| ((i * 7) % 11) as f32 / 10.0 - 0.5)
+        .collect();
+    Kernel::new((width, height), weights).unwrap()
+}
+
+/// Check the GPU matches the CPU reference for a kernel size
+fn check_size(size: (u32, u32)) {
+    check_kernel(kernel(size));
+}
+
+/// Check the GPU matches the CPU reference for a kernel
+fn check_kernel(kernel: Kernel) {
+    let size = kernel.size();
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let source = common::lena();
+    context.load_image(&source).unwrap();
+    let display = ImageDisplay {
+        scaling_mode: ScalingMode::NearestNeighbour,
+        size: 0.25,
+        filters: FilterGraph::standard(Some(kernel.clone())),
+        ..Default::default()
+    };
+    common::gpu_matches_cpu(&mut context, &source, display, &format!("{size:?}"));
+    let stage = context.stages.kernels().next().unwrap();
+    assert_eq!(
+        stage.separable_kernel.is_some(),
+        size.0 > 1 && size.1 > 1 && kernel.separate().is_some()
+    );
+}
+
+#[test]
+fn square_3x3() {
+    check_size((3, 3));
+}
+
+#[test]
+fn wide_7x3() {
+    check_size((7, 3));
+}
+
+#[test]
+fn tall_1x7() {
+    check_size((1, 7));
+}
+
+#[test]
+fn largest_31x31() {
+    check_size((31, 31));
+}
+
+#[test]
+fn fractional_weights() {
+    // Gaussian weights which are not multiples of 1/256
+    let weights = [1.0, 4.0, 6.0, 4.0, 1.0];
+    let weights = (0..25)
+        .map(|i| weights[i / 5] * weights[i % 5] / 256.0)
+        .collect();
+    check_kernel(Kernel::new((5, 5), weights).unwrap());
+}
+
+#[test]
+fn large_weights() {
+    // Weights outside of -128.0-128.0 are not clamped
+    let kernel = Kernel::new((3, 1), vec![-150.0, 300.0, -150.0]).unwrap();
+    check_kernel(kernel);
+
+    // Responses outside of -128.0-128.0 are stored unclipped
+    let img = Rgba32FImage::from_pixel(3, 1, image::Rgba([0.5, 0.5, 0.5, 1.0]));
+    let kerneled = cpu::apply_kernel(
+        &img,
+        &Kernel::new((1, 1), vec![-300.0]).unwrap(),
+        Default::default(),
+        false,
+    );
+    assert_eq!(kerneled.get_pixel(1, 0)[0], -150.0);
+}
+
+/// Outer product of a column and a row
+fn outer(column: &[f32], row: &[f32]) -> Kernel {
+    let weights = column
+        .iter()
+        .flat_map(|c| row.iter().map(move |r| c * r))
+        .collect();
+    Kernel::new((row.len() as u32, column.len() as u32), weights).unwrap()
+}
+
+#[test]
+fn separable_kernels() {
+    let gaussian = outer(&[1.0, 4.0, 6.0, 4.0, 1.0], &[1.0, 4.0, 6.0, 4.0, 1.0]);
+    let sobel = outer(&[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0]);
+    let skewed = outer(&[0.5, -2.0, 3.0], &[0.25, 1.0, 0.0, -7.0, 2.0, 0.1, 3.0]);
+    for kernel in [&gaussian, &sobel, &skewed] {
+        let (row, column) = kernel.separate().unwrap();
+        assert_eq!(row.size(), (kernel.size().0, 1));
+        assert_eq!(column.size(), (1, kernel.size().1));
+        let product = outer(column.weights(), row.weights());
+        for (a, b) in product.weights().iter().zip(kernel.weights()) {
+            assert!(
+                (a - b).abs() < 1e-5,
+                "{kernel:?} was not split into {row:?} {column:?}"
+            );
+        }
+    }
+
+    // Full rank and zero kernels fall back to the 2D path
+    assert!(Kernel::laplacian().separate().is_none());
+    assert!(Kernel::new((3, 3), vec![0.0; 9])
+        .unwrap()
+        .separate()
+        .is_none());
+    let mut perturbed = gaussian.clone();
+    perturbed.weights_mut()[3] += 0.01;
+    assert!(perturbed.separate().is_none());
+}
+
+#[test]
+fn separable_gpu_matches_2d_cpu() {
+    // The CPU reference always evaluates the full 2D kernel
+    check_kernel(outer(&[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0]));
+    check_kernel(outer(
+        &[1.0, 4.0, 6.0, 4.0, 1.0],
+        &[1.0, 6.0, 15.0, 20.0, 15.0, 6.0, 1.0].map(|w| w / 64.0),
+    ));
+}
+
+#[test]
+fn parse_sizes() {
+    assert_eq!(Kernel::parse("laplacian").unwrap(), Kernel::laplacian());
+    assert_eq!(Kernel::parse("0,1,0,1,-4,1,0,1,0").unwrap().size(), (3, 3));
+    assert_eq!(Kernel::parse("1,2,1").unwrap().size(), (3, 1));
+    assert_eq!(Kernel::parse("1;2;1").unwrap().size(), (1, 3));
+    assert_eq!(Kernel::parse("1,2,1;2,4,2;1,2,1").unwrap().size(), (3, 3));
+
+    // Even, mismatched and oversized kernels are rejected
+    assert!(Kernel::parse("1,2").is_err());
+    assert!(Kernel::parse("1,2,1;2,4").is_err());
+    assert!(Kernel::parse(&vec!["0"; 33].join(",")).is_err());
+}
+
+#[test]
+fn resize_keeps_centre() {
+    let mut kernel = Kernel::laplacian();
+    kernel.resize((3, 1)).unwrap();
+    assert_eq!(kernel.weights(), &[3.0, 4.0, 3.0]);
+
+    kernel.resize((5, 3)).unwrap();
+    assert_eq!(
+        kernel.weights(),
+        &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 3.0, 4.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+    );
+    assert!(kernel.resize((4, 3)).is_err());
+}
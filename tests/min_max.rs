@@ -0,0 +1,129 @@
+mod common;
+
+use cs256::{
+    cpu,
+    graph::FilterGraph,
+    headless::HeadlessContext,
+    image_display::{ImageDisplay, ScalingMode},
+    kernel::Kernel,
+    pipelines::{MinMaxPipelines, Pipelines},
+    stages::RenderStages,
+};
+use image::{DynamicImage, Rgba, RgbaImage};
+
+/// Kernel which leaves the image unchanged
+fn identity() -> Kernel {
+    Kernel::new((1, 1), vec![1.0]).unwrap()
+}
+
+/// Create a grey image with its max in the bottom right pixel and its min in
+/// the bottom left pixel, both outside of any whole 8x8 chunk for odd sizes
+fn image((width, height): (u32, u32)) -> DynamicImage {
+    let mut img = RgbaImage::from_pixel(width, height, Rgba([128, 128, 128, 255]));
+    img.put_pixel(0, height - 1, Rgba([0, 0, 0, 255]));
+    img.put_pixel(width - 1, height - 1, Rgba([255, 255, 255, 255]));
+    DynamicImage::ImageRgba8(img)
+}
+
+fn display() -> ImageDisplay {
+    ImageDisplay {
+        scaling_mode: ScalingMode::NearestNeighbour,
+        size: 1.0,
+        gamma: 1.0,
+        filters: FilterGraph::standard(Some(identity())),
+        ..Default::default()
+    }
+}
+
+/// Expected min and max of the kernelled image
+fn expected((width, height): (u32, u32)) -> (f32, f32) {
+    match (width, height) {
+        (1, 1) => (1.0, 1.0),
+        _ => (0.0, 1.0),
+    }
+}
+
+/// Check the CPU reference finds the exact min and max of the kernelled image
+fn cpu_exact(dims: (u32, u32)) {
+    let linear = cpu::to_linear(&image(dims));
+    let (mini, maxi) = cpu::min_max(&cpu::apply_kernel(
+        &linear,
+        &identity(),
+        Default::default(),
+        false,
+    ));
+    let (expected_min, expected_max) = expected(dims);
+    assert!((mini - expected_min).abs() < 1e-4, "{dims:?}: min {mini}");
+    assert!((maxi - expected_max).abs() < 1e-4, "{dims:?}: max {maxi}");
+}
+
+/// Rebuild the pipelines of a context to reduce the min and max with fragment
+/// shaders, as on adapters without compute shaders
+fn use_fragment_reduction(context: &mut HeadlessContext) {
+    context.pipelines = pollster::block_on(Pipelines::new(
+        &context.device,
+        HeadlessContext::OFFSCREEN_FORMAT,
+        &context.image_display.layout,
+        false,
+    ))
+    .unwrap();
+    context.stages = RenderStages::new();
+    assert!(matches!(
+        context.pipelines.min_max,
+        MinMaxPipelines::Fragment(_)
+    ));
+}
+
+/// Check the GPU output matches the CPU reference and is normalized over the
+/// full range, mapping the extremes to the ends of the lookup table
+fn gpu_exact(dims: (u32, u32), compute_shaders: bool) {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    if !compute_shaders {
+        use_fragment_reduction(&mut context);
+    }
+    let source = image(dims);
+    context.load_image(&source).unwrap();
+    let (gpu, cpu) = common::process(&mut context, &source, display());
+    let case = format!("{dims:?} compute {compute_shaders}");
+    common::assert_close(&gpu, &cpu, cpu::tolerance(1.0), &case);
+
+    let lut = cpu::gamma_lut(1.0);
+    let (width, height) = dims;
+    let corner = |x, y| gpu.get_pixel(x, y)[0];
+    match dims {
+        // A flat image has no range so maps to the start of the table
+        (1, 1) => assert!((corner(0, 0) - lut[0]).abs() <= cpu::TOLERANCE),
+        _ => {
+            assert!((corner(0, height - 1) - lut[0]).abs() <= cpu::TOLERANCE);
+            assert!((corner(width - 1, height - 1) - lut[255]).abs() <= cpu::TOLERANCE);
+        }
+    }
+}
+
+#[test]
+fn single_pixel() {
+    cpu_exact((1, 1));
+    gpu_exact((1, 1), true);
+}
+
+#[test]
+fn smaller_than_chunk() {
+    cpu_exact((7, 13));
+    gpu_exact((7, 13), true);
+}
+
+#[test]
+fn odd_size() {
+    cpu_exact((1023, 769));
+    gpu_exact((1023, 769), true);
+}
+
+#[test]
+fn fragment_reduction() {
+    // Adapters without compute shaders reduce with fragment passes instead
+    for dims in [(1, 1), (7, 13), (1023, 769)] {
+        gpu_exact(dims, false);
+    }
+}
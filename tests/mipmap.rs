@@ -0,0 +1,115 @@
+mod common;
+
+use cs256::{
+    cpu,
+    headless::HeadlessContext,
+    image_display::{ImageDisplay, ScalingMode},
+    stages::MipChain,
+};
+use image::{DynamicImage, Rgba, Rgba32FImage, RgbaImage};
+
+/// Create a checkerboard of single black and white pixels, which aliases
+/// to a single colour when point sampled at a power of two scale
+fn checkerboard((width, height): (u32, u32)) -> DynamicImage {
+    DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+        match (x + y) % 2 == 0 {
+            true => Rgba([255, 255, 255, 255]),
+            false => Rgba([0, 0, 0, 255]),
+        }
+    }))
+}
+
+/// Render the source and read back the interpolated image
+fn interpolate(context: &mut HeadlessContext, display: ImageDisplay) -> Rgba32FImage {
+    *context.image_display_mut() = display;
+    context.image_display.set_changed();
+    context.render();
+    pollster::block_on(context.read_back(context.stages.output_staging())).unwrap()
+}
+
+/// Check the GPU matches the CPU reference with and without mipmaps
+fn check_mipmaps(source: &DynamicImage, scaling_mode: ScalingMode, size: f32) {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    context.load_image(source).unwrap();
+
+    for mipmaps in [false, true] {
+        let display = ImageDisplay {
+            scaling_mode,
+            size,
+            mipmaps,
+            ..Default::default()
+        };
+        let case = format!("{scaling_mode:?} {size} mipmaps {mipmaps}");
+        common::gpu_matches_cpu(&mut context, source, display, &case);
+    }
+}
+
+#[test]
+fn nearest_levels() {
+    for size in [0.7, 0.3, 0.125, 0.01] {
+        check_mipmaps(&common::lena(), ScalingMode::NearestNeighbour, size);
+    }
+}
+
+#[test]
+fn bilinear_blends_levels() {
+    for size in [0.7, 0.3, 0.125, 0.01] {
+        check_mipmaps(&common::lena(), ScalingMode::Bilinear, size);
+    }
+}
+
+#[test]
+fn odd_sizes() {
+    // Odd rows and columns are dropped, down to a single pixel wide level
+    let source = common::lena().crop_imm(3, 5, 61, 13);
+    check_mipmaps(&source, ScalingMode::Bilinear, 0.2);
+    check_mipmaps(&source, ScalingMode::NearestNeighbour, 0.05);
+}
+
+#[test]
+fn chain_sizes() {
+    for (dims, sizes) in [
+        ((1, 1), vec![(1, 1), (1, 1)]),
+        ((8, 2), vec![(8, 2), (4, 1), (2, 1), (1, 1)]),
+        ((5, 3), vec![(5, 3), (2, 1), (1, 1)]),
+    ] {
+        let levels = cpu::mip_chain(Rgba32FImage::new(dims.0, dims.1));
+        let found: Vec<(u32, u32)> = levels.iter().map(|l| l.dimensions()).collect();
+        assert_eq!(found, sizes);
+        assert_eq!(MipChain::level_count(dims) as usize, levels.len() - 1);
+    }
+}
+
+#[test]
+fn removes_aliasing() {
+    // Every output pixel covers a 4x4 block, half black and half white
+    let source = checkerboard((64, 64));
+    let linear = cpu::to_linear(&source);
+    let levels = cpu::mip_chain(linear.clone());
+    let averaged = cpu::nearest_neighbour(&levels, (16, 16), 0.25, Default::default());
+    let aliased = cpu::nearest_neighbour(&[linear], (16, 16), 0.25, Default::default());
+    for (p, q) in averaged.pixels().zip(aliased.pixels()) {
+        assert!((p[0] - 0.5).abs() < 1e-6, "{p:?}");
+        assert!(q[0] == 0.0 || q[0] == 1.0, "{q:?}");
+    }
+
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    context.load_image(&source).unwrap();
+    for scaling_mode in [ScalingMode::NearestNeighbour, ScalingMode::Bilinear] {
+        let display = ImageDisplay {
+            scaling_mode,
+            size: 0.25,
+            ..Default::default()
+        };
+        for p in interpolate(&mut context, display).pixels() {
+            assert!(
+                (p[0] - 0.5).abs() <= cpu::TOLERANCE,
+                "{scaling_mode:?}: {p:?}"
+            );
+        }
+    }
+}
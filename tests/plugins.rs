@@ -0,0 +1,306 @@
+mod common;
+
+use std::{borrow::Cow, path::PathBuf};
+
+use cs256::{
+    graph::{Filter, FilterGraph, FilterNode},
+    headless::HeadlessContext,
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+    plugins::{self, Param, ParamKind, ParamValue},
+    shaders::{self, Diagnostic, Preprocessed},
+};
+use image::{imageops, DynamicImage, Rgba, Rgba32FImage};
+
+/// A plugin with a parameter of every kind
+const PARAMS: &str = "
+#include \"plugin.wgsl\"
+
+struct Params {
+    strength: f32, // @range(-2, 2) @default(0.5)
+    radius: i32,
+    count: u32, // @range(1, 4)
+    // Shown as a checkbox
+    flip: u32, // @checkbox @default(true)
+};
+
+@group(PARAMS_GROUP) @binding(0)
+var<uniform> params: Params;
+
+fn apply(colour: vec4<f32>, pixel: vec2<i32>) -> vec4<f32> {
+    return colour * params.strength;
+}
+";
+
+/// A plugin replacing red and green with the position of the pixel
+const POSITION: &str = "
+#include \"plugin.wgsl\"
+
+fn apply(colour: vec4<f32>, pixel: vec2<i32>) -> vec4<f32> {
+    let position = vec2<f32>(pixel % vec2<i32>(7, 5)) / vec2<f32>(6.0, 4.0);
+    return vec4<f32>(position, colour.b, colour.a);
+}
+";
+
+/// Preprocess a plugin, its includes are the embedded shaders
+fn preprocess(name: &str, source: &'static str) -> Preprocessed {
+    shaders::preprocess(name, |file| match file == name {
+        true => Ok(Cow::Borrowed(source)),
+        false => shaders::source(file),
+    })
+    .unwrap()
+}
+
+fn reflect(source: &'static str) -> Result<(Vec<plugins::Param>, u32), Diagnostic> {
+    plugins::reflect(&preprocess("test.wgsl", source))
+}
+
+/// Render a float image, returning the first pixel
+fn render(context: &mut HeadlessContext) -> [f32; 4] {
+    context.render();
+    let output = pollster::block_on(context.read_output()).unwrap();
+    output.into_rgba32f().get_pixel(0, 0).0
+}
+
+/// Add a node of a loaded plugin to the end of the graph, returning its index
+fn add(context: &mut HeadlessContext, plugin: usize) -> usize {
+    let filter = Filter::plugin(&context.plugins[plugin]);
+    let nodes = &mut context.image_display_mut().filters.nodes;
+    nodes.push(FilterNode::new(filter, true));
+    nodes.len() - 1
+}
+
+/// Parameters of a plugin node in the graph
+fn params(context: &mut HeadlessContext, node: usize) -> &mut Vec<Param> {
+    match &mut context.image_display_mut().filters.nodes[node].filter {
+        Filter::Plugin { params, .. } => params,
+        _ => panic!("node {node} is not a plugin"),
+    }
+}
+
+fn assert_close(a: [f32; 4], b: [f32; 4]) {
+    for i in 0..4 {
+        assert!((a[i] - b[i]).abs() < 1e-4, "{a:?} != {b:?}");
+    }
+}
+
+#[test]
+fn reflection() {
+    let (params, size) = reflect(PARAMS).unwrap();
+    assert_eq!(size, 16);
+    let names: Vec<_> = params.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["strength", "radius", "count", "flip"]);
+    assert_eq!(
+        params.iter().map(|p| p.offset).collect::<Vec<_>>(),
+        [0, 4, 8, 12]
+    );
+
+    assert_eq!(
+        params[0].kind,
+        ParamKind::Float {
+            min: -2.0,
+            max: 2.0
+        }
+    );
+    assert_eq!(params[0].default, ParamValue::Float(0.5));
+    // Unannotated parameters default to the start of their range
+    assert_eq!(params[1].kind, ParamKind::Int { min: 0, max: 10 });
+    assert_eq!(params[1].value, ParamValue::Int(0));
+    assert_eq!(params[2].kind, ParamKind::Uint { min: 1, max: 4 });
+    assert_eq!(params[2].value, ParamValue::Uint(1));
+    assert_eq!(params[3].kind, ParamKind::Bool);
+    assert_eq!(params[3].value, ParamValue::Bool(true));
+
+    // Every plugin shipped with the repository reflects
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("plugins");
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        let source = std::fs::read_to_string(&path).unwrap().leak();
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let shader = preprocess(name, source);
+        assert_eq!(shaders::validate(&shader), Ok(()), "{name}");
+        assert!(!plugins::reflect(&shader).unwrap().0.is_empty(), "{name}");
+    }
+}
+
+#[test]
+fn without_params() {
+    let source = "
+#include \"plugin.wgsl\"
+
+fn apply(colour: vec4<f32>, pixel: vec2<i32>) -> vec4<f32> {
+    return colour;
+}
+";
+    assert_eq!(reflect(source), Ok((Vec::new(), 0)));
+}
+
+#[test]
+fn invalid_params() {
+    // Located on the line of the parameter
+    let error = reflect(&*PARAMS.replace("radius: i32", "radius: vec2<f32>").leak()).unwrap_err();
+    assert_eq!(error.file, "test.wgsl");
+    assert_eq!(error.location.map(|(line, _)| line), Some(6), "{error}");
+    assert!(error.message.contains("radius"), "{error}");
+
+    for (from, to) in [
+        ("@checkbox", "@slider"),
+        ("@range(1, 4)", "@range(1)"),
+        ("@default(0.5)", "@default(half)"),
+        ("@range(1, 4)", "@checkbox @range(1, 4)"),
+    ] {
+        let source = PARAMS.replace(from, to).leak();
+        assert!(reflect(source).is_err(), "{to}");
+    }
+}
+
+#[test]
+fn filters() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let img = Rgba32FImage::from_pixel(4, 4, Rgba([0.2, 0.4, 0.9, 1.0]));
+    context
+        .load_image(&DynamicImage::ImageRgba32F(img))
+        .unwrap();
+
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("plugins");
+    assert!(pollster::block_on(context.load_plugins(&dir)).is_empty());
+    let names: Vec<_> = context.plugins.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["adjust.wgsl", "posterize.wgsl"]);
+
+    // Plugins which are not in the graph leave the image unchanged
+    assert_close(render(&mut context), [0.2, 0.4, 0.9, 1.0]);
+
+    // Added at their defaults the adjustments do nothing
+    let adjust = add(&mut context, 0);
+    assert_close(render(&mut context), [0.2, 0.4, 0.9, 1.0]);
+
+    params(&mut context, adjust)[3].value = ParamValue::Bool(true);
+    assert_close(render(&mut context), [0.8, 0.6, 0.1, 1.0]);
+
+    // Plugins are chained in order, the inverted colour is posterized
+    let posterize = add(&mut context, 1);
+    params(&mut context, posterize)[0].value = ParamValue::Int(2);
+    assert_close(render(&mut context), [1.0, 1.0, 0.0, 1.0]);
+
+    // A duplicated node inverts the colour back, while a disabled node is skipped
+    context.image_display_mut().filters.duplicate(adjust);
+    assert_close(render(&mut context), [0.0, 0.0, 1.0, 1.0]);
+    context.image_display_mut().filters.nodes[adjust + 1].enabled = false;
+    assert_close(render(&mut context), [1.0, 1.0, 0.0, 1.0]);
+}
+
+#[test]
+fn reload() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let dir = std::env::temp_dir().join(format!("cs256-plugins-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("params.wgsl");
+    std::fs::write(&path, PARAMS).unwrap();
+
+    assert!(pollster::block_on(context.load_plugins(&dir)).is_empty());
+    let node = add(&mut context, 0);
+    params(&mut context, node)[0].value = ParamValue::Float(1.5);
+
+    // A plugin which fails keeps its previous pipeline and its nodes their values
+    std::fs::write(&path, "fn (").unwrap();
+    let errors = pollster::block_on(context.load_plugins(&dir));
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert_eq!(errors[0].file, "params.wgsl");
+    assert_eq!(context.plugins.len(), 1);
+
+    // Reloaded, parameters which are unchanged keep their values
+    let source = PARAMS.replace("strength: f32", "strength: f32, extra: f32");
+    std::fs::write(&path, source).unwrap();
+    assert!(pollster::block_on(context.load_plugins(&dir)).is_empty());
+    let params = params(&mut context, node).clone();
+    assert_eq!(params[0].value, ParamValue::Float(1.5));
+    assert_eq!(params[1].name, "extra");
+
+    // Removed plugins are unloaded, as is a missing directory. Their nodes
+    // stay in the graph but are skipped
+    std::fs::remove_file(&path).unwrap();
+    assert!(pollster::block_on(context.load_plugins(&dir)).is_empty());
+    assert!(context.plugins.is_empty());
+    assert_eq!(context.filters().count(), 1);
+    context.render();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(pollster::block_on(context.load_plugins(&dir)).is_empty());
+}
+
+#[test]
+fn default_dir() {
+    // The default does not depend on the working directory
+    std::env::remove_var(plugins::DIR_VAR);
+    let exe = std::env::current_exe().unwrap();
+    let expected = exe.parent().unwrap().join(plugins::DEFAULT_DIR);
+    assert_eq!(plugins::dir(), Some(expected));
+
+    std::env::set_var(plugins::DIR_VAR, "elsewhere");
+    assert_eq!(plugins::dir(), Some(PathBuf::from("elsewhere")));
+    std::env::remove_var(plugins::DIR_VAR);
+}
+
+#[test]
+fn position_in_tiles_and_viewport() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let dir = std::env::temp_dir().join(format!("cs256-position-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("position.wgsl"), POSITION).unwrap();
+    assert!(pollster::block_on(context.load_plugins(&dir)).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+    let source = common::lena().crop_imm(0, 0, 120, 90);
+    context.load_image(&source).unwrap();
+    let tile_size = context.max_tile_size;
+
+    // The plugin runs before a kernel, over its apron, and after it
+    let position = Filter::plugin(&context.plugins[0]);
+    let nodes = vec![
+        FilterNode::new(position.clone(), true),
+        FilterNode::new(Filter::Kernel(Kernel::laplacian()), true),
+        FilterNode::new(position, true),
+    ];
+    for border_mode in BorderMode::ALL {
+        *context.image_display_mut() = ImageDisplay {
+            scaling_mode: ScalingMode::Bilinear,
+            size: 1.3,
+            border_mode,
+            filters: FilterGraph {
+                nodes: nodes.clone(),
+            },
+            window_size: [37.0, 29.0],
+            pos: [12.0, -7.0],
+            ..Default::default()
+        };
+        let case = format!("{border_mode:?}");
+        let whole = pollster::block_on(context.read_output())
+            .unwrap()
+            .into_rgba32f();
+
+        // Tiles see the position within the whole image
+        context.max_tile_size = 50;
+        assert!(context.tiles().len() > 1);
+        let tiled = pollster::block_on(context.read_output())
+            .unwrap()
+            .into_rgba32f();
+        context.max_tile_size = tile_size;
+        common::assert_close(&tiled, &whole, 1e-5, &format!("tiled {case}"));
+
+        // As does the viewport
+        context.viewport = true;
+        context.render();
+        let visible = context.regions().visible;
+        let processed = context.stages.processed();
+        let viewport = pollster::block_on(context.read_back(processed)).unwrap();
+        context.viewport = false;
+        let (x, y) = visible.origin;
+        let (width, height) = visible.size;
+        let expected = imageops::crop_imm(&whole, x as u32, y as u32, width, height).to_image();
+        common::assert_close(&viewport, &expected, 1e-5, &format!("viewport {case}"));
+    }
+}
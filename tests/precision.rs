@@ -0,0 +1,105 @@
+mod common;
+
+use std::collections::HashSet;
+
+use cs256::{
+    cpu, graph::FilterGraph, headless::HeadlessContext, image_display::ImageDisplay,
+    kernel::Kernel, tiles::SourcePrecision,
+};
+use image::{DynamicImage, ImageBuffer, Rgba, Rgba32FImage};
+
+/// Render a source with the display, checking the GPU matches the CPU reference
+fn check_precision(
+    context: &mut HeadlessContext,
+    source: &DynamicImage,
+    display: ImageDisplay,
+) -> Rgba32FImage {
+    context.load_image(source).unwrap();
+    let (gpu, cpu) = common::process(context, source, display);
+    assert_eq!(gpu.dimensions(), cpu.dimensions());
+    for (x, y, p) in gpu.enumerate_pixels() {
+        let q = cpu.get_pixel(x, y);
+        for i in 0..4 {
+            // Float images reach beyond 0..1, so the tolerance is relative
+            let tolerance = cpu::TOLERANCE * q[i].abs().max(1.0);
+            assert!(
+                (p[i] - q[i]).abs() <= tolerance,
+                "at ({x}, {y}): {p:?} != {q:?}"
+            );
+        }
+    }
+    gpu
+}
+
+#[test]
+fn precision_of_images() {
+    let of = SourcePrecision::of;
+    assert_eq!(of(&DynamicImage::new_rgb8(1, 1)), SourcePrecision::Srgb8);
+    assert_eq!(of(&DynamicImage::new_luma_a8(1, 1)), SourcePrecision::Srgb8);
+    assert_eq!(of(&DynamicImage::new_luma16(1, 1)), SourcePrecision::Srgb16);
+    assert_eq!(of(&DynamicImage::new_rgba16(1, 1)), SourcePrecision::Srgb16);
+    assert_eq!(of(&DynamicImage::new_rgb32f(1, 1)), SourcePrecision::Float);
+    assert!(!SourcePrecision::Srgb8.exact_gamma());
+    assert!(SourcePrecision::Float.exact_gamma());
+}
+
+#[test]
+fn sixteen_bit() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    // Split into tiles, so tiles are read from the middle of the wider pixels
+    context.max_tile_size = 300;
+
+    // A gradient with 1024 steps, finer than an 8-bit image can hold
+    let img = ImageBuffer::from_fn(1024, 3, |x, y| {
+        let x = x as u16 * 64;
+        Rgba([x, 65535 - x, y as u16 * 20000, 65535])
+    });
+    let source = DynamicImage::ImageRgba16(img);
+    let gpu = check_precision(&mut context, &source, ImageDisplay::default());
+    assert_eq!(context.source.precision(), SourcePrecision::Srgb16);
+    assert_eq!(context.source.grid(), (4, 1));
+
+    let steps: HashSet<u32> = gpu.pixels().map(|p| p[0].to_bits()).collect();
+    assert!(steps.len() > 1000, "only {} steps", steps.len());
+
+    let display = ImageDisplay {
+        gamma: 2.2,
+        filters: FilterGraph::standard(Some(Kernel::laplacian())),
+        ..Default::default()
+    };
+    check_precision(&mut context, &source, display);
+}
+
+#[test]
+fn float_unclamped() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+
+    // Linear values outside of 0..1, as HDR and EXR images hold
+    let img = Rgba32FImage::from_fn(16, 16, |x, y| {
+        Rgba([x as f32 * 0.5 - 2.0, y as f32 * 0.25, 3.0, 1.0])
+    });
+    let source = DynamicImage::ImageRgba32F(img.clone());
+
+    // Without any gamma the image is unchanged
+    let gpu = check_precision(&mut context, &source, ImageDisplay::default());
+    assert_eq!(context.source.precision(), SourcePrecision::Float);
+    for (p, q) in gpu.pixels().zip(img.pixels()) {
+        for i in 0..4 {
+            assert!((p[i] - q[i]).abs() < 1e-5, "{p:?} != {q:?}");
+        }
+    }
+
+    for gamma in [0.6, 1.8] {
+        let display = ImageDisplay {
+            gamma,
+            size: 1.5,
+            filters: FilterGraph::standard((gamma > 1.0).then(Kernel::laplacian)),
+            ..Default::default()
+        };
+        check_precision(&mut context, &source, display);
+    }
+}
@@ -0,0 +1,19 @@
+mod common;
+
+use cs256::stages::RenderGroup;
+
+/// A texture too large for a single buffer is reported instead of panicking
+#[test]
+fn larger_than_buffer_limit() {
+    let Some(context) = common::gpu() else { return };
+    let max_size = context.device.limits().max_buffer_size;
+
+    // Rows of 4096 pixels are 64 KiB, so one more row than fits in the limit
+    let rows = (max_size / (4096 * 16) + 1) as u32;
+    let group = RenderGroup::new(&context, (4096, rows), wgpu::TextureFormat::Rgba32Float);
+    let error = group
+        .start_read(&context.device, &context.queue)
+        .err()
+        .unwrap();
+    assert!(error.to_string().contains("buffer limit"), "{error}");
+}
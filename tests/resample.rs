@@ -0,0 +1,104 @@
+mod common;
+
+use cs256::{
+    cpu::{self, Border},
+    image_display::{ImageDisplay, ScalingMode},
+};
+use image::{Rgba, Rgba32FImage};
+
+/// Scaling modes which resample with a filter
+const FILTERS: [ScalingMode; 5] = [
+    ScalingMode::Bicubic,
+    ScalingMode::Mitchell,
+    ScalingMode::Lanczos2,
+    ScalingMode::Lanczos3,
+    ScalingMode::Box,
+];
+
+/// Create an image with a different value in every pixel
+fn ramp((width, height): (u32, u32)) -> Rgba32FImage {
+    Rgba32FImage::from_fn(width, height, |x, y| {
+        let v = ((x * 7 + y * 13) % 17) as f32 / 16.0;
+        Rgba([v, 1.0 - v, v * v, 1.0])
+    })
+}
+
+/// Check the GPU matches the CPU reference for every filter at a scale
+fn check_filters(size: f32) {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let source = common::lena();
+    context.load_image(&source).unwrap();
+
+    for scaling_mode in FILTERS {
+        let display = ImageDisplay {
+            scaling_mode,
+            size,
+            ..Default::default()
+        };
+        common::gpu_matches_cpu(&mut context, &source, display, &format!("{scaling_mode:?}"));
+    }
+}
+
+#[test]
+fn upscale() {
+    check_filters(1.7);
+}
+
+#[test]
+fn downscale() {
+    check_filters(0.3);
+}
+
+#[test]
+fn box_averages_area() {
+    let img = ramp((8, 6));
+    let halved = cpu::resample(&img, (4, 3), 0.5, ScalingMode::Box, Border::default());
+    for (x, y, p) in halved.enumerate_pixels() {
+        for i in 0..4 {
+            let average = (0..4)
+                .map(|j| img.get_pixel(x * 2 + j % 2, y * 2 + j / 2)[i])
+                .sum::<f32>()
+                / 4.0;
+            assert!((p[i] - average).abs() < 1e-6, "({x}, {y}): {p:?}");
+        }
+    }
+}
+
+#[test]
+fn interpolating_filters_keep_pixels() {
+    // Catmull-Rom and Lanczos pass through the original pixels at scale 1
+    let img = ramp((9, 7));
+    for mode in [
+        ScalingMode::Bicubic,
+        ScalingMode::Lanczos2,
+        ScalingMode::Lanczos3,
+        ScalingMode::Box,
+    ] {
+        let same = cpu::resample(&img, (9, 7), 1.0, mode, Border::default());
+        for (p, q) in same.pixels().zip(img.pixels()) {
+            for i in 0..4 {
+                assert!((p[i] - q[i]).abs() < 1e-5, "{mode:?}: {p:?} != {q:?}");
+            }
+        }
+    }
+}
+
+#[test]
+fn constant_images_stay_constant() {
+    let img = Rgba32FImage::from_pixel(11, 5, Rgba([0.25, 0.5, 0.75, 1.0]));
+    for mode in FILTERS {
+        for (scale, dims) in [(2.3, (25, 11)), (0.4, (4, 2))] {
+            let resampled = cpu::resample(&img, dims, scale, mode, Border::default());
+            for p in resampled.pixels() {
+                for i in 0..4 {
+                    assert!(
+                        (p[i] - img.get_pixel(0, 0)[i]).abs() < 1e-5,
+                        "{mode:?}: {p:?}"
+                    );
+                }
+            }
+        }
+    }
+}
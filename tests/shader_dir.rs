@@ -0,0 +1,92 @@
+mod common;
+
+use std::borrow::Cow;
+
+use cs256::{headless::HeadlessContext, shaders};
+use image::{DynamicImage, Rgba, Rgba32FImage};
+
+/// A gamma correction stage that outputs a constant grey
+const GREY: &str = "
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(position, 1.0);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(0.25);
+}
+";
+
+/// Render the loaded image, returning the red channel of the first pixel
+fn render_red(context: &mut HeadlessContext) -> f32 {
+    context.render();
+    let output = pollster::block_on(context.read_output()).unwrap();
+    output.into_rgba32f().get_pixel(0, 0)[0]
+}
+
+// The only test in this binary, as setting the override directory
+// changes the shaders of every context created while it is set
+#[test]
+fn override_dir() {
+    let dir = std::env::temp_dir().join(format!("cs256-shaders-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::env::set_var(shaders::OVERRIDE_VAR, &dir);
+    let mut watcher = shaders::Watcher::new(dir.clone());
+
+    // Shaders missing from the directory are still embedded
+    assert!(matches!(
+        shaders::source("output.wgsl").unwrap(),
+        Cow::Borrowed(_)
+    ));
+
+    let path = dir.join("gamma_correction.wgsl");
+    std::fs::write(&path, GREY).unwrap();
+    assert_eq!(shaders::source("gamma_correction.wgsl").unwrap(), GREY);
+
+    // The watcher polls the directory, finding the shader once it has been written
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert_eq!(watcher.changed(), ["gamma_correction.wgsl"]);
+    assert!(watcher.changed().is_empty());
+
+    if let Some(mut context) = common::gpu() {
+        let img = Rgba32FImage::from_pixel(4, 4, Rgba([1.0, 0.0, 0.5, 1.0]));
+        context
+            .load_image(&DynamicImage::ImageRgba32F(img))
+            .unwrap();
+        assert!((render_red(&mut context) - 0.25).abs() < 1e-3);
+
+        // A reload that fails keeps the previous pipeline
+        std::fs::write(&path, "fn (").unwrap();
+        let errors = pollster::block_on(context.reload_shaders());
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        let error = &errors[0];
+        assert_eq!(error.file, "gamma_correction.wgsl");
+        assert_eq!(error.location.map(|(line, _)| line), Some(1), "{error}");
+        assert!((render_red(&mut context) - 0.25).abs() < 1e-3);
+
+        // Removing the shader reloads the embedded shader
+        std::fs::remove_file(&path).unwrap();
+        assert!(pollster::block_on(context.reload_shaders()).is_empty());
+        assert!((render_red(&mut context) - 1.0).abs() < 1e-3);
+
+        // Dropped first, as the GL backend fails to drop a device
+        // once another instance has been created
+        drop(context);
+
+        // A shader that fails to parse is an error naming the file
+        std::fs::write(&path, "fn (").unwrap();
+        let result = pollster::block_on(HeadlessContext::new(true));
+        let error = format!("{:#}", result.err().unwrap());
+        assert!(error.contains("gamma_correction.wgsl"), "{error}");
+    }
+
+    std::env::remove_var(shaders::OVERRIDE_VAR);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use std::borrow::Cow;
+
+use anyhow::{anyhow, Result};
+use cs256::shaders::{self, Preprocessed};
+
+/// Every shader the pipelines load
+const NAMES: [&str; 10] = [
+    "interpolation.wgsl",
+    "mipmap.wgsl",
+    "kernel.wgsl",
+    "min_max.wgsl",
+    "min_max_reduce.wgsl",
+    "normalize.wgsl",
+    "gamma_lookup.wgsl",
+    "gamma_correction.wgsl",
+    "convert.wgsl",
+    "output.wgsl",
+];
+
+/// Preprocess a shader reading files from a list rather than the shader directory
+fn preprocess(name: &str, files: &[(&'static str, &'static str)]) -> Preprocessed {
+    try_preprocess(name, files).unwrap()
+}
+
+fn try_preprocess(
+    name: &str,
+    files: &[(&'static str, &'static str)],
+) -> Result<Preprocessed, shaders::Diagnostic> {
+    let files = files.to_vec();
+    shaders::preprocess(name, move |name: &str| -> Result<Cow<'static, str>> {
+        files
+            .iter()
+            .find(|(file, _)| *file == name)
+            .map(|(_, source)| Cow::Borrowed(*source))
+            .ok_or_else(|| anyhow!("No shader named {name}"))
+    })
+}
+
+#[test]
+fn embedded() {
+    for name in NAMES {
+        let source = shaders::embedded(name).unwrap();
+        assert!(source.contains("fn "), "{name}");
+
+        // Every include is embedded, and expanded into the shader
+        let shader = shaders::preprocess(name, shaders::source).unwrap();
+        assert!(!shader.source.contains('#'), "{name}");
+        assert_eq!(shaders::validate(&shader), Ok(()));
+    }
+    assert!(shaders::embedded("missing.wgsl").is_none());
+    assert!(shaders::source("missing.wgsl").is_err());
+}
+
+#[test]
+fn parse_error() {
+    let Some(context) = common::gpu() else { return };
+    let shader = preprocess("broken.wgsl", &[("broken.wgsl", "fn (")]);
+    let result = pollster::block_on(shaders::create_module(&context.device, &shader));
+    let error = result.err().unwrap().to_string();
+    assert!(error.contains("broken.wgsl"), "{error}");
+}
+
+#[test]
+fn diagnostics() {
+    // A syntax error is located where parsing stopped
+    let shader = preprocess("broken.wgsl", &[("broken.wgsl", "fn main() {}\nfn (")]);
+    let error = shaders::validate(&shader).unwrap_err();
+    assert_eq!(error.file, "broken.wgsl");
+    assert_eq!(error.location, Some((2, 4)));
+    assert!(
+        error.to_string().starts_with("broken.wgsl:2:4: "),
+        "{error}"
+    );
+
+    // A type error is found by validation, the message holds its causes
+    let source = "fn main() -> f32 {\n    return 1u;\n}";
+    let error =
+        shaders::validate(&preprocess("types.wgsl", &[("types.wgsl", source)])).unwrap_err();
+    assert_eq!(error.location.map(|(line, _)| line), Some(2), "{error}");
+    assert!(error.message.contains("main"), "{error}");
+}
+
+#[test]
+fn includes() {
+    let files = [
+        (
+            "main.wgsl",
+            "#define SIZE 4\n#include \"a.wgsl\"\n  #include \"b.wgsl\"\n#include \"a.wgsl\"\nfn main() {}",
+        ),
+        ("a.wgsl", "// A uses SIZE\nconst A_SIZE: i32 = SIZE; // SIZE of main"),
+        ("b.wgsl", "#include \"a.wgsl\"\n#define SIZE 8\nconst B: i32 = SIZE + A_SIZE;"),
+    ];
+
+    // Each file is included once, defines apply to every following line
+    // including those of included files, and can be redefined. Comments are
+    // left as they are
+    let shader = preprocess("main.wgsl", &files);
+    assert_eq!(
+        shader.source,
+        "// A uses SIZE\nconst A_SIZE: i32 = 4; // SIZE of main\nconst B: i32 = 8 + A_SIZE;\nfn main() {}\n"
+    );
+    assert_eq!(shader.files(), ["main.wgsl", "a.wgsl", "b.wgsl"]);
+    assert_eq!(shaders::validate(&shader), Ok(()));
+
+    // Errors are located in the file and line they came from
+    let files = [files[0], files[1], ("b.wgsl", "\n\nconst B: i32 = 1u;")];
+    let error = shaders::validate(&preprocess("main.wgsl", &files)).unwrap_err();
+    assert_eq!(error.file, "b.wgsl");
+    assert_eq!(error.location.map(|(line, _)| line), Some(3), "{error}");
+
+    // A missing include is an error at the include
+    let error = try_preprocess("main.wgsl", &files[..2]).err().unwrap();
+    assert_eq!(
+        (error.file.as_str(), error.location),
+        ("main.wgsl", Some((3, 3)))
+    );
+    assert!(error.message.contains("b.wgsl"), "{error}");
+
+    // As are malformed and unknown directives
+    for source in ["#include a.wgsl", "#define 1", "#if A"] {
+        let error = try_preprocess("main.wgsl", &[("main.wgsl", source)])
+            .err()
+            .unwrap();
+        assert_eq!(error.location, Some((1, 1)), "{source}: {error}");
+    }
+}
+
+#[test]
+fn reload_embedded() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    context.render();
+    let before = pollster::block_on(context.read_output()).unwrap();
+
+    // Rebuilding every pipeline from the same shaders renders the same image
+    assert!(pollster::block_on(context.reload_shaders()).is_empty());
+    context.render();
+    let after = pollster::block_on(context.read_output()).unwrap();
+    assert_eq!(before.as_bytes(), after.as_bytes());
+}
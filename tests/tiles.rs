@@ -0,0 +1,168 @@
+mod common;
+
+use cs256::{
+    graph::{Filter, FilterGraph, FilterNode},
+    headless::HeadlessContext,
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+    tiles::SourceTiles,
+};
+
+/// Tile size forced on the context, much smaller than the images loaded
+const TILE_SIZE: u32 = 50;
+
+/// Create a headless context splitting images into small tiles, None if there is no adapter
+fn context() -> Option<HeadlessContext> {
+    let mut context = common::gpu()?;
+    context.max_tile_size = TILE_SIZE;
+    Some(context)
+}
+
+#[test]
+fn split() {
+    // Only axes larger than the limit are split, into a power of two
+    assert_eq!(SourceTiles::split((512, 512), 8192), ((512, 512), (1, 1)));
+    assert_eq!(SourceTiles::split((512, 90), 100), ((64, 90), (8, 1)));
+    assert_eq!(
+        SourceTiles::split((20000, 15000), 8192),
+        ((8192, 8192), (3, 2))
+    );
+}
+
+#[test]
+fn scaling_modes() {
+    let Some(mut context) = context() else { return };
+    let source = common::lena().crop_imm(3, 5, 200, 150);
+    context.load_image(&source).unwrap();
+    assert_eq!(context.source.tile_size(), (32, 32));
+    assert_eq!(context.source.grid(), (7, 5));
+
+    for scaling_mode in ScalingMode::ALL {
+        for size in [1.7, 0.6, 0.15] {
+            let display = ImageDisplay {
+                scaling_mode,
+                size,
+                ..Default::default()
+            };
+            common::gpu_matches_cpu(
+                &mut context,
+                &source,
+                display,
+                &format!("{scaling_mode:?} {size}"),
+            );
+        }
+    }
+}
+
+#[test]
+fn border_modes() {
+    let Some(mut context) = context() else { return };
+    let source = common::lena();
+    context.load_image(&source).unwrap();
+
+    for border_mode in BorderMode::ALL {
+        for scaling_mode in [ScalingMode::Bilinear, ScalingMode::Lanczos3] {
+            let display = ImageDisplay {
+                scaling_mode,
+                border_mode,
+                size: 0.45,
+                filters: FilterGraph::standard(Some(Kernel::laplacian())),
+                ..Default::default()
+            };
+            common::gpu_matches_cpu(
+                &mut context,
+                &source,
+                display,
+                &format!("{scaling_mode:?} {border_mode:?}"),
+            );
+        }
+    }
+}
+
+#[test]
+fn processed_in_tiles() {
+    let Some(mut context) = context() else { return };
+    let source = common::lena().crop_imm(0, 0, 120, 90);
+    context.load_image(&source).unwrap();
+    // Square tiles in a multiple of six layers, which GL would make a cube map
+    assert_eq!(context.source.grid(), (4, 3));
+
+    // Two kernels in series, so the second is normalized by the range of the
+    // whole image as normalized by the first
+    let blur = Kernel::new((3, 5), vec![1.0; 15]).unwrap();
+    let mut nodes: Vec<_> = [Kernel::laplacian(), blur]
+        .into_iter()
+        .map(|kernel| FilterNode::new(Filter::Kernel(kernel), true))
+        .collect();
+    nodes.push(FilterNode::new(Filter::Gamma, true));
+    let filters = FilterGraph { nodes };
+
+    for border_mode in BorderMode::ALL {
+        let display = ImageDisplay {
+            scaling_mode: ScalingMode::Bilinear,
+            border_mode,
+            size: 1.3,
+            gamma: 1.2,
+            filters: filters.clone(),
+            ..Default::default()
+        };
+        *context.image_display_mut() = display.clone();
+
+        // Every stage of every tile fits within the limit
+        let tiles = context.tiles();
+        assert!(tiles.len() > 4, "{border_mode:?}: {} tiles", tiles.len());
+        for regions in &tiles {
+            let mut sizes = vec![regions.interpolated.size, regions.visible.size];
+            for kernel in &regions.kernels {
+                sizes.extend([kernel.source.size, kernel.separable().0.size]);
+            }
+            for size in sizes {
+                assert!(size.0 <= TILE_SIZE && size.1 <= TILE_SIZE, "{size:?}");
+            }
+        }
+
+        common::gpu_matches_cpu(&mut context, &source, display, &format!("{border_mode:?}"));
+    }
+}
+
+#[test]
+fn default_tile_size() {
+    // Tiles are limited by the largest buffer a tile can be read back in
+    let limits = wgpu::Limits::default();
+    assert_eq!(HeadlessContext::default_tile_size(&limits), 4096);
+    let webgl2 = wgpu::Limits::downlevel_webgl2_defaults();
+    assert_eq!(HeadlessContext::default_tile_size(&webgl2), 2048);
+
+    // An output wider than a tile is processed and read back in tiles
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let tile_size = context.max_tile_size;
+    assert_eq!(
+        tile_size,
+        HeadlessContext::default_tile_size(&context.device.limits())
+    );
+    let source = common::lena().crop_imm(0, 0, 256, 32);
+    context.load_image(&source).unwrap();
+    let display = ImageDisplay {
+        scaling_mode: ScalingMode::Bilinear,
+        size: tile_size as f32 * 1.1 / 256.0,
+        filters: FilterGraph::standard(Some(Kernel::laplacian())),
+        ..Default::default()
+    };
+    *context.image_display_mut() = display.clone();
+    assert_eq!(context.tiles().len(), 2);
+    common::gpu_matches_cpu(&mut context, &source, display, "default tile size");
+}
+
+#[test]
+fn too_many_tiles() {
+    let Some(mut context) = context() else { return };
+    let size = context.source.size();
+    context.max_tile_size = 1;
+    let error = context.load_image(&common::lena()).unwrap_err();
+    assert!(error.to_string().contains("tiles"), "{error}");
+
+    // The previous image is kept
+    assert_eq!(context.source.size(), size);
+}
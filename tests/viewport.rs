@@ -0,0 +1,242 @@
+mod common;
+
+use cs256::{
+    cpu::{self, Border},
+    graph::FilterGraph,
+    headless::HeadlessContext,
+    image_display::{BorderMode, ImageDisplay, ScalingMode},
+    kernel::Kernel,
+    region::Region,
+};
+use image::{imageops, Rgba32FImage};
+
+/// Size of the window the viewport is taken from
+const WINDOW: [f32; 2] = [97.0, 61.0];
+
+/// Zoom applied to the image, so it is much larger than the window
+const SIZE: f32 = 3.0;
+
+/// Kernel which is separable, with a different width and height
+fn sobel_5x3() -> Kernel {
+    let weights = [1.0, 2.0, 1.0]
+        .iter()
+        .flat_map(|c| [-1.0, -2.0, 0.0, 2.0, 1.0].map(|r| c * r))
+        .collect();
+    Kernel::new((5, 3), weights).unwrap()
+}
+
+/// Positions placing the window over the centre and both corners of an image
+/// of the given size, with part of the window outside the image at the corners
+fn positions((width, height): (u32, u32)) -> [[f32; 2]; 3] {
+    let corner = |size: u32, window: f32| size as f32 / 2.0 - window / 2.0 + 20.0;
+    let (x, y) = (corner(width, WINDOW[0]), corner(height, WINDOW[1]));
+    [[0.0, 0.0], [x, y], [-x, -y]]
+}
+
+fn crop(img: &Rgba32FImage, region: Region) -> Rgba32FImage {
+    let (x, y) = region.origin;
+    let (width, height) = region.size;
+    imageops::crop_imm(img, x as u32, y as u32, width, height).to_image()
+}
+
+/// Check the GPU only processes the visible region and matches the CPU reference
+/// for every border mode and window position
+fn check_viewport(scaling_mode: ScalingMode, kernel: Option<Kernel>) {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    let source = common::lena();
+    let linear = cpu::to_linear(&source);
+    context.load_image(&source).unwrap();
+    context.viewport = true;
+
+    for border_mode in BorderMode::ALL {
+        // The whole image as it is saved, which is the same at every position
+        let mut saved = None;
+        for pos in positions(context.regions().output) {
+            *context.image_display_mut() = ImageDisplay {
+                window_size: WINDOW,
+                pos,
+                scaling_mode,
+                size: SIZE,
+                filters: FilterGraph::standard(kernel.clone()),
+                border_mode,
+                ..Default::default()
+            };
+            context.render();
+
+            let regions = context.regions();
+            let display = context.image_display();
+            let case = format!("{border_mode:?} {pos:?}");
+            let mut staging = cpu::interpolate(linear.clone(), display);
+            if let Some(kernel) = &kernel {
+                // The kernel amplifies differences in decoding sRGB by the sum of its
+                // weights, so the later stages are checked against the kerneled image
+                // read back. It is normalized with the min and max of the whole image
+                let full =
+                    cpu::apply_kernel(&staging, kernel, Border::from_display(display), false);
+                let kerneled = crop(&full, regions.visible);
+                let stage = context.stages.kernels().next().unwrap();
+                let gpu = pollster::block_on(context.read_back(&stage.kerneled)).unwrap();
+                let sum: f32 = kernel.weights().iter().map(|w| w.abs()).sum();
+                common::assert_close(&gpu, &kerneled, cpu::TOLERANCE * sum, &case);
+                staging = cpu::normalize(&gpu, cpu::min_max(&full), false);
+            } else {
+                staging = crop(&staging, regions.visible);
+            }
+
+            let cpu = cpu::gamma_correction(&staging, &cpu::gamma_lut(display.gamma), false);
+            let gamma = context.stages.nodes().last().unwrap().output();
+            let gpu = pollster::block_on(context.read_back(gamma)).unwrap();
+            assert_eq!(gpu.dimensions(), regions.visible.size);
+            common::assert_close(&gpu, &cpu, cpu::tolerance(1.0), &case);
+
+            // The viewport shows the same pixels as the saved image
+            let processed = context.stages.processed();
+            let viewport = pollster::block_on(context.read_back(processed)).unwrap();
+            let saved = saved.get_or_insert_with(|| {
+                pollster::block_on(context.read_output())
+                    .unwrap()
+                    .into_rgba32f()
+            });
+            let saved = crop(saved, regions.visible);
+            common::assert_close(&viewport, &saved, cpu::TOLERANCE, &format!("saved {case}"));
+        }
+    }
+}
+
+#[test]
+fn interpolation_only() {
+    check_viewport(ScalingMode::Bilinear, None);
+}
+
+#[test]
+fn full_kernel() {
+    check_viewport(ScalingMode::NearestNeighbour, Some(Kernel::laplacian()));
+}
+
+#[test]
+fn separable_kernel() {
+    check_viewport(ScalingMode::Bilinear, Some(sobel_5x3()));
+}
+
+#[test]
+fn bounded_by_window() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    context.viewport = true;
+    *context.image_display_mut() = ImageDisplay {
+        window_size: WINDOW,
+        size: context.max_scale(),
+        filters: FilterGraph::standard(Some(Kernel::laplacian())),
+        ..Default::default()
+    };
+    context.render();
+
+    // Only the window and the kernel apron are processed, however large the zoom
+    let regions = context.stages.regions().clone();
+    let (width, height) = (WINDOW[0] as u32 + 1, WINDOW[1] as u32 + 1);
+    assert!(regions.scaled.0 > width * 10);
+    assert!(regions.visible.size.0 <= width && regions.visible.size.1 <= height);
+    let apron = Kernel::laplacian().size();
+    assert!(regions.interpolated.size.0 <= width + apron.0);
+    assert!(regions.interpolated.size.1 <= height + apron.1);
+    assert_eq!(
+        context.stages.output_staging().size(),
+        regions.interpolated.size
+    );
+    assert_eq!(context.stages.processed().size(), regions.visible.size);
+}
+
+/// Render frames until the ranges of the whole image are found, returning the
+/// number of frames rendered
+fn render_until_found(context: &mut HeadlessContext) -> usize {
+    let mut frames = 0;
+    loop {
+        context.render();
+        frames += 1;
+        if !context.range_stages.has_ranges() {
+            return frames;
+        }
+    }
+}
+
+#[test]
+fn ranges_across_frames() {
+    let Some(mut context) = common::gpu() else {
+        return;
+    };
+    context.load_image(&common::lena()).unwrap();
+    context.viewport = true;
+    context.max_tile_size = 200;
+    context.ranges_per_frame = 1;
+    *context.image_display_mut() = ImageDisplay {
+        window_size: WINDOW,
+        size: SIZE,
+        filters: FilterGraph::standard(Some(Kernel::laplacian())),
+        ..Default::default()
+    };
+
+    // A pass over each tile is run each frame, the frames before have no ranges
+    // so they are normalized with the viewport alone
+    let tiles = context.tiles().len();
+    assert!(tiles > 1);
+    context.render();
+    assert!(context.stages.ranges_found().is_empty());
+    assert_eq!(1 + render_until_found(&mut context), tiles);
+    assert_eq!(context.stages.ranges_found().len(), 1);
+
+    // The last frame is normalized with the ranges of the whole image
+    let check_saved = |context: &mut HeadlessContext, case: &str| {
+        let processed = context.stages.processed();
+        let viewport = pollster::block_on(context.read_back(processed)).unwrap();
+        let saved = pollster::block_on(context.read_output()).unwrap();
+        let saved = crop(&saved.into_rgba32f(), context.regions().visible);
+        common::assert_close(&viewport, &saved, cpu::TOLERANCE, case);
+    };
+    check_saved(&mut context, "found");
+
+    // Settings after the kernel and the unused border colour keep the ranges
+    let display = context.image_display_mut();
+    display.gamma = 2.2;
+    display.background_colour = [1.0, 0.0, 0.0, 1.0];
+    display.border_colour = [0.0, 1.0, 0.0, 1.0];
+    display.pos = [10.0, -5.0];
+    context.render();
+    assert!(!context.range_stages.has_ranges());
+    check_saved(&mut context, "cached");
+
+    // A new kernel keeps the previous ranges until its own are found
+    context.image_display_mut().filters = FilterGraph::standard(Some(sobel_5x3()));
+    context.render();
+    assert!(context.range_stages.has_ranges());
+    assert_eq!(context.stages.ranges_found().len(), 1);
+    assert_eq!(1 + render_until_found(&mut context), context.tiles().len());
+    check_saved(&mut context, "new kernel");
+}
+
+#[test]
+fn visible_region() {
+    let display = ImageDisplay {
+        window_size: [100.0, 80.0],
+        ..Default::default()
+    };
+
+    // Centred on the window
+    let region = display.visible_region((768, 768));
+    assert_eq!(region.origin, (334, 344));
+    assert_eq!(region.size, (100, 80));
+
+    // Smaller than the window, so the whole image is visible
+    assert_eq!(display.visible_region((50, 40)), Region::whole((50, 40)));
+
+    // Moved so only the top left corner is visible
+    let display = ImageDisplay {
+        pos: [390.5, 398.0],
+        ..display
+    };
+    let region = display.visible_region((768, 768));
+    assert_eq!(region.origin, (0, 0));
+    assert_eq!(region.size, (44, 26));
+}